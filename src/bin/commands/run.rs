@@ -3,33 +3,82 @@
 // See the LICENSE file in the project root for more information.
 
 use std::env;
+use std::path::PathBuf;
 
+use cakeup::{self, CakeupResult, Config, ConfigFile};
 use clap::{App, ArgMatches};
-use cakeup::{self, CakeupResult, Config};
 
 use crate::commands::Command;
 
 pub struct RunCommand {}
 impl Command for RunCommand {
     fn run(&self, app: App) -> CakeupResult<i32> {
-        let config = parse(app);
+        let config = parse(app)?;
         return cakeup::run(config);
     }
 }
 
-pub fn parse(app: App) -> Config {
+pub fn parse(app: App) -> CakeupResult<Config> {
     let root = env::current_dir().unwrap();
     let tools = root.join("tools");
 
     let matches = app.get_matches();
     let args = matches.subcommand_matches("run").unwrap();
 
-    let cake_version = parse_string_option(args, "cake", "CAKEUP_CAKE", "none", false);
-    let nuget_version = parse_string_option(args, "nuget", "CAKEUP_NUGET", "none", false);
-    let sdk_version = parse_string_option(args, "sdk", "CAKEUP_SDK", "none", false);
-    let bootstrap = parse_bool(args, "bootstrap", "CAKEUP_BOOTSTRAP");
-    let use_coreclr = parse_bool(args, "coreclr", "CAKEUP_CORECLR");
-    let execute_script = parse_bool(args, "execute", "CAKEUP_EXECUTE");
+    // Read the configuration file (if any).
+    let file = ConfigFile::load(&root)?.unwrap_or_default();
+
+    let cake_version = parse_string_option(args, "cake", "CAKEUP_CAKE", &file.cake, "none", false);
+    let nuget_version =
+        parse_string_option(args, "nuget", "CAKEUP_NUGET", &file.nuget, "none", false);
+    let sdk_versions: Vec<String> = parse_list(args, "sdk", "CAKEUP_SDK", &file.get_sdk_versions())
+        .into_iter()
+        .filter(|v| v != "none")
+        .collect();
+    let sdk_policy = parse_string(
+        args,
+        "sdk-policy",
+        "CAKEUP_SDK_POLICY",
+        &file.sdk_policy,
+        "feature",
+    );
+    let runtimes = parse_list(args, "runtime", "CAKEUP_RUNTIME", &file.runtimes);
+    let sources = parse_list(args, "source", "CAKEUP_SOURCE", &file.sources);
+    let bootstrap = parse_bool(args, "bootstrap", "CAKEUP_BOOTSTRAP", file.bootstrap);
+    let use_coreclr = parse_bool(args, "coreclr", "CAKEUP_CORECLR", file.coreclr);
+    let execute_script = parse_bool(args, "execute", "CAKEUP_EXECUTE", file.execute);
+    let locked = parse_bool(args, "locked", "CAKEUP_LOCKED", file.locked);
+    let offline = parse_bool(args, "offline", "CAKEUP_OFFLINE", file.offline);
+    let quiet = parse_bool(args, "quiet", "CAKEUP_QUIET", file.quiet);
+    let prerelease = parse_bool(args, "prerelease", "CAKEUP_PRERELEASE", file.prerelease);
+    let global_packages = parse_bool(
+        args,
+        "global-packages",
+        "CAKEUP_GLOBAL_PACKAGES",
+        file.global_packages,
+    );
+    let retries = parse_number(args, "retries", "CAKEUP_RETRIES", file.retries, 3)?;
+    let lock_timeout = parse_number(
+        args,
+        "lock-timeout",
+        "CAKEUP_LOCK_TIMEOUT",
+        file.lock_timeout,
+        300,
+    )?;
+    let proxy = parse_string_option(args, "proxy", "CAKEUP_PROXY", &file.proxy, "", false);
+    let ca_bundle = parse_string_option(
+        args,
+        "ca-bundle",
+        "CAKEUP_CA_BUNDLE",
+        &file.ca_bundle,
+        "",
+        false,
+    )
+    .map(|path| root.join(path));
+    let cache = match env::var("CAKEUP_CACHE") {
+        Ok(ref path) if path != "" => Some(PathBuf::from(path)),
+        _ => file.cache.as_ref().map(|path| root.join(path)),
+    };
 
     let mut remaining: Vec<String> = vec![];
     let raw_remaining = args.values_of("remaining")
@@ -39,29 +88,45 @@ pub fn parse(app: App) -> Config {
         for arg in raw_remaining.unwrap() {
             remaining.push(String::from(arg));
         }
+    } else if file.args.is_some() {
+        remaining = file.args.unwrap();
     }
 
-    return Config {
+    return Ok(Config {
         root,
         tools,
         bootstrap,
         cake_version,
         nuget_version,
-        sdk_version,
+        sdk_versions,
+        sdk_policy,
+        runtimes,
+        sources,
         use_coreclr,
         execute_script,
+        locked,
+        retries,
+        lock_timeout: u64::from(lock_timeout),
+        cache,
+        offline,
+        global_packages,
+        proxy,
+        ca_bundle,
+        quiet,
+        prerelease,
         remaining
-    };
+    });
 }
 
 fn parse_string_option(
     matches: &ArgMatches,
     arg_name: &str,
     env_name: &str,
+    file_value: &Option<String>,
     default: &str,
     prefix: bool,
 ) -> Option<String> {
-    let value = parse_string(matches, arg_name, env_name, default);
+    let value = parse_string(matches, arg_name, env_name, file_value, default);
     return match value.as_ref() {
         "" => None,
         "none" => None,
@@ -75,9 +140,21 @@ fn parse_string_option(
     };
 }
 
-fn parse_string(matches: &ArgMatches, arg_name: &str, env_name: &str, default: &str) -> String {
+fn parse_string(
+    matches: &ArgMatches,
+    arg_name: &str,
+    env_name: &str,
+    file_value: &Option<String>,
+    default: &str,
+) -> String {
     return match matches.value_of(arg_name) {
-        None => String::from(env::var(env_name).unwrap_or(String::from(default))),
+        None => match env::var(env_name) {
+            Ok(ref v) if v != "" => v.clone(),
+            _ => match file_value {
+                Some(v) => v.clone(),
+                None => String::from(default),
+            },
+        },
         Some(v) => match &v[..] {
             "" => String::from(default),
             _ => String::from(v),
@@ -85,7 +162,62 @@ fn parse_string(matches: &ArgMatches, arg_name: &str, env_name: &str, default: &
     };
 }
 
-fn parse_bool(matches: &ArgMatches, arg_name: &str, env_name: &str) -> bool {
+fn parse_list(
+    matches: &ArgMatches,
+    arg_name: &str,
+    env_name: &str,
+    file_value: &Option<Vec<String>>,
+) -> Vec<String> {
+    if let Some(values) = matches.values_of(arg_name) {
+        return values.map(|v| String::from(v)).collect();
+    }
+    // Multiple values in environment variables are separated by semicolons.
+    if let Ok(value) = env::var(env_name) {
+        if value != "" {
+            return value
+                .split(';')
+                .filter(|v| *v != "")
+                .map(|v| String::from(v))
+                .collect();
+        }
+    }
+    return match file_value {
+        Some(values) => values.clone(),
+        None => vec![],
+    };
+}
+
+fn parse_number(
+    matches: &ArgMatches,
+    arg_name: &str,
+    env_name: &str,
+    file_value: Option<u32>,
+    default: u32,
+) -> CakeupResult<u32> {
+    let file_value = file_value.map(|v| v.to_string());
+    let value = parse_string(
+        matches,
+        arg_name,
+        env_name,
+        &file_value,
+        &default.to_string()[..],
+    );
+    return match value.parse::<u32>() {
+        Ok(n) => Ok(n),
+        Err(_) => Err(format_err!(
+            "The value '{}' of --{} is not a valid number.",
+            value,
+            arg_name
+        )),
+    };
+}
+
+fn parse_bool(
+    matches: &ArgMatches,
+    arg_name: &str,
+    env_name: &str,
+    file_value: Option<bool>,
+) -> bool {
     if matches.is_present(arg_name) {
         return true;
     }
@@ -96,5 +228,5 @@ fn parse_bool(matches: &ArgMatches, arg_name: &str, env_name: &str) -> bool {
             return value.to_lowercase() == "true";
         }
     }
-    return false;
+    return file_value.unwrap_or(false);
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
 #[macro_use]
+extern crate failure;
+#[macro_use]
 extern crate log;
 extern crate cakeup;
 extern crate clap;
@@ -42,7 +44,56 @@ pub fn main() {
                     Arg::with_name("sdk")
                         .takes_value(true)
                         .long("sdk")
-                        .help("The version of the .NET Core SDK to install."),
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("A version of the .NET Core SDK to install."),
+                )
+                .arg(
+                    Arg::with_name("sdk-policy")
+                        .takes_value(true)
+                        .long("sdk-policy")
+                        .possible_values(&["exact", "feature", "major"])
+                        .help("Which installed .NET Core SDKs can be used instead of the wanted one."),
+                )
+                .arg(
+                    Arg::with_name("runtime")
+                        .takes_value(true)
+                        .long("runtime")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("A .NET Core runtime to install, such as dotnet:2.1.30 or aspnetcore:3.1.x."),
+                )
+                .arg(
+                    Arg::with_name("source")
+                        .takes_value(true)
+                        .long("source")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("A NuGet package source to install Cake from."),
+                )
+                .arg(
+                    Arg::with_name("proxy")
+                        .takes_value(true)
+                        .long("proxy")
+                        .help("The proxy to use for downloads."),
+                )
+                .arg(
+                    Arg::with_name("ca-bundle")
+                        .takes_value(true)
+                        .long("ca-bundle")
+                        .help("A file with the CA certificates to trust."),
+                )
+                .arg(
+                    Arg::with_name("retries")
+                        .takes_value(true)
+                        .long("retries")
+                        .help("The number of times to retry failed downloads."),
+                )
+                .arg(
+                    Arg::with_name("lock-timeout")
+                        .takes_value(true)
+                        .long("lock-timeout")
+                        .help("The number of seconds to wait for other cakeup processes."),
                 )
                 .arg(
                     Arg::with_name("execute")
@@ -59,6 +110,32 @@ pub fn main() {
                         .long("coreclr")
                         .help("Use the CoreCLR version of Cake."),
                 )
+                .arg(
+                    Arg::with_name("locked")
+                        .long("locked")
+                        .help("Fails if cakeup.lock needs to be updated."),
+                )
+                .arg(
+                    Arg::with_name("global-packages")
+                        .long("global-packages")
+                        .help("Adds downloaded Cake packages to the global NuGet packages folder."),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .short("q")
+                        .long("quiet")
+                        .help("Doesn't report download progress."),
+                )
+                .arg(
+                    Arg::with_name("prerelease")
+                        .long("prerelease")
+                        .help("Allows prerelease versions of Cake and the .NET Core SDK."),
+                )
+                .arg(
+                    Arg::with_name("offline")
+                        .long("offline")
+                        .help("Only installs things that are available locally."),
+                )
                 .arg(
                     Arg::with_name("remaining")
                         .help("Arguments that will be sent to Cake.")
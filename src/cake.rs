@@ -3,40 +3,64 @@
 // See the LICENSE file in the project root for more information.
 
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use failure;
 use semver::Version;
 
+use crate::feed;
+use crate::global_packages;
 use crate::host::Host;
+use crate::lockfile::{self, LockEntry, Lockfile};
+use crate::offline::MissingOffline;
+use crate::utils::cache::Cache;
+use crate::utils::filelock::FileLock;
+use crate::utils::version::VersionSpec;
 use crate::utils::CakeupResult;
-use crate::utils::{http, zip};
+use crate::utils::{hash, zip};
 use crate::Config;
 
+/// Written to a package directory once the package has been completely installed.
+const COMPLETE_MARKER: &'static str = ".cakeup-complete";
+
 pub struct Package {
     pub name: String,
     pub version: Version,
     pub filename: String,
     pub directory: PathBuf,
+    pub staging_directory: PathBuf,
+    pub download_path: PathBuf,
+    pub lock_path: PathBuf,
     pub core_clr: bool,
 }
 
 impl Package {
     pub fn new(config: &Config, version: &Version) -> Self {
-        let name = if config.use_coreclr {
-            "Cake.CoreClr"
-        } else {
-            "Cake"
-        };
+        let name = get_package_name(config);
         let directory = config
             .tools
             .join(format!("{0}.{1}", name.to_lowercase(), version));
+        let staging_directory =
+            config
+                .tools
+                .join(format!(".{0}.{1}.staging", name.to_lowercase(), version));
+        let download_path =
+            config
+                .tools
+                .join(format!(".{0}.{1}.nupkg", name.to_lowercase(), version));
+        let lock_path = config
+            .tools
+            .join(format!(".{0}.{1}.lock", name.to_lowercase(), version));
         let filename = format!("{}.{}.nupkg", name.to_lowercase(), version);
         return Package {
             name: name.to_string(),
             version: version.clone(),
             core_clr: config.use_coreclr,
             directory,
+            staging_directory,
+            download_path,
+            lock_path,
             filename,
         };
     }
@@ -45,11 +69,8 @@ impl Package {
         return self.directory.join(&self.name);
     }
 
-    pub fn get_url(&self) -> String {
-        return format!(
-            "https://www.nuget.org/api/v2/package/{0}/{1}",
-            self.name, self.version
-        );
+    pub fn is_installed(&self) -> bool {
+        return self.directory.join(COMPLETE_MARKER).exists();
     }
 }
 
@@ -96,19 +117,19 @@ pub fn should_install(config: &Config) -> bool {
     return config.cake_version != None;
 }
 
-pub fn install(config: &Config) -> CakeupResult<Option<Cake>> {
+pub fn install(config: &Config, lockfile: &mut Lockfile) -> CakeupResult<Option<Cake>> {
     if !should_install(&config) {
         return Ok(Option::None);
     }
 
     // Get the version we're going to use.
-    let version = match Version::parse(&config.cake_version.as_ref().unwrap()[..]) {
-        Ok(v) => v,
-        Err(_) => return Err(failure::err_msg("Provided Cake version is not valid.")),
-    };
+    let version = resolve_version(config, lockfile)?;
 
     let package = Package::new(config, &version);
-    install_package(&package)?;
+    let version = format!("{}", version);
+    let expected_hash = lockfile::get_locked_hash(&lockfile.cake, Some(&package.name), &version);
+    let hash = install_package(config, &package, &expected_hash)?;
+    lockfile.cake = Some(LockEntry::new(Some(&package.name), &version, hash));
 
     return Ok(Option::Some(Cake {
         path: get_executable_path(&package),
@@ -117,59 +138,342 @@ pub fn install(config: &Config) -> CakeupResult<Option<Cake>> {
     }));
 }
 
-fn install_package(package: &Package) -> CakeupResult<()> {
-    if !package.directory.exists() {
-        trace!("Creating package directory...");
-        fs::create_dir(package.directory.to_str().unwrap())?;
+fn get_package_name(config: &Config) -> &'static str {
+    if config.use_coreclr {
+        return "Cake.CoreClr";
+    }
+    return "Cake";
+}
+
+fn resolve_version(config: &Config, lockfile: &Lockfile) -> CakeupResult<Version> {
+    let spec = match VersionSpec::parse(&config.cake_version.as_ref().unwrap()[..]) {
+        Ok(spec) => spec,
+        Err(_) => return Err(failure::err_msg("Provided Cake version is not valid.")),
+    };
+    let name = get_package_name(config);
 
-        let cake_nupkg_path = package.get_path();
-        if !cake_nupkg_path.exists() {
-            fetch_package(&package)?;
+    // Use the locked version if it satisfies the wanted version.
+    if let Some(version) =
+        lockfile::get_locked_version(lockfile.cake.as_ref(), Some(name), &spec, config.prerelease)
+    {
+        trace!(
+            "Using {} ({}) from {}.",
+            name,
+            version,
+            lockfile::LOCK_FILENAME
+        );
+        return Ok(version);
+    }
+    lockfile::ensure_unlocked(config, name)?;
+
+    return match spec {
+        VersionSpec::Exact(version) => Ok(version),
+        _ if config.offline => resolve_offline_version(config, &spec),
+        VersionSpec::Latest => {
+            info!("Resolving latest version of {}...", name);
+            let feeds = feed::open_sources(config)?;
+            let version = feed::get_latest_version(&feeds, name, config.prerelease)?;
+            info!("Resolved latest version of {} to {}.", name, version);
+            Ok(version)
         }
-        trace!("Unzipping {} binaries...", package.name);
-        zip::unzip(&cake_nupkg_path, &package.directory)?;
-        info!("Installed {} ({}).", package.name, package.version);
-    } else {
+        VersionSpec::Range(_) => {
+            // Prefer a version that is already installed.
+            let installed = get_installed_versions(config)?;
+            if let Some(version) = spec.find_best(&installed, config.prerelease) {
+                info!("Using installed {} ({}) matching {}.", name, version, spec);
+                return Ok(version);
+            }
+
+            info!("Resolving version of {} matching {}...", name, spec);
+            let feeds = feed::open_sources(config)?;
+            let versions = feed::get_versions(&feeds, name)?;
+            match spec.find_best(&versions, config.prerelease) {
+                Some(version) => {
+                    info!("Resolved {} {} to {}.", name, spec, version);
+                    Ok(version)
+                }
+                None => Err(format_err!(
+                    "Could not find a version of {} matching {}.",
+                    name,
+                    spec
+                )),
+            }
+        }
+    };
+}
+
+fn resolve_offline_version(config: &Config, spec: &VersionSpec) -> CakeupResult<Version> {
+    let name = get_package_name(config);
+    info!("Resolving version of {} matching {} offline...", name, spec);
+
+    // Consider everything that can be installed without the network.
+    let mut versions = get_installed_versions(config)?;
+    versions.extend(get_global_package_versions(config)?);
+    versions.extend(get_cached_versions(config)?);
+    let feeds = feed::open_sources(config)?;
+    versions.extend(feed::get_versions(&feeds, name)?);
+
+    return match spec.find_best(&versions, config.prerelease) {
+        Some(version) => {
+            info!("Resolved {} {} to {}.", name, spec, version);
+            Ok(version)
+        }
+        None => Err(MissingOffline(format!("{} ({})", name, spec)).into()),
+    };
+}
+
+fn get_installed_versions(config: &Config) -> CakeupResult<Vec<Version>> {
+    let mut versions = Vec::new();
+    if !config.tools.exists() {
+        return Ok(versions);
+    }
+
+    // Package directories are named <id>.<version>.
+    let prefix = format!("{}.", get_package_name(config).to_lowercase());
+    for entry in fs::read_dir(&config.tools)? {
+        let entry = entry?;
+        if !entry.path().join(COMPLETE_MARKER).exists() {
+            continue;
+        }
+        let filename = entry.file_name();
+        let filename = filename.to_string_lossy();
+        if filename.starts_with(&prefix[..]) {
+            if let Ok(version) = Version::parse(&filename[prefix.len()..]) {
+                versions.push(version);
+            }
+        }
+    }
+    return Ok(versions);
+}
+
+fn get_global_package_versions(config: &Config) -> CakeupResult<Vec<Version>> {
+    let mut versions = Vec::new();
+    let name = get_package_name(config).to_lowercase();
+    let path = match global_packages::get_path(config)? {
+        Some(path) => path.join(&name),
+        None => return Ok(versions),
+    };
+    if !path.is_dir() {
+        return Ok(versions);
+    }
+
+    // Packages are stored as <id>/<version>/<id>.<version>.nupkg.
+    for entry in fs::read_dir(&path)? {
+        let entry = entry?;
+        let filename = entry.file_name();
+        let filename = filename.to_string_lossy();
+        if let Ok(version) = Version::parse(&filename[..]) {
+            let nupkg = format!("{}.{}.nupkg", name, version);
+            if entry.path().join(nupkg).exists() {
+                versions.push(version);
+            }
+        }
+    }
+    return Ok(versions);
+}
+
+fn get_cached_versions(config: &Config) -> CakeupResult<Vec<Version>> {
+    let mut versions = Vec::new();
+    let cache = match Cache::new(config) {
+        Some(cache) => cache,
+        None => return Ok(versions),
+    };
+
+    let name = get_package_name(config);
+    for entry in cache.list(&["packages", name])? {
+        if let Ok(version) = Version::parse(&entry[..]) {
+            let filename = format!("{}.{}.nupkg", name.to_lowercase(), version);
+            if cache.contains(&["packages", name, &entry[..], &filename[..]]) {
+                versions.push(version);
+            }
+        }
+    }
+    return Ok(versions);
+}
+
+fn install_package(
+    config: &Config,
+    package: &Package,
+    expected_hash: &Option<String>,
+) -> CakeupResult<Option<String>> {
+    let _lock = FileLock::acquire(&package.lock_path, Duration::from_secs(config.lock_timeout))?;
+    if package.is_installed() {
         info!(
             "{} ({}) is already installed.",
             package.name, &package.version
         );
+        return verify_package(&package, &package.get_path(), expected_hash);
     }
-    return Ok(());
+
+    // Remove what's left from an interrupted installation.
+    if package.directory.exists() {
+        warn!(
+            "The installation of {} ({}) is incomplete. Reinstalling...",
+            package.name, &package.version
+        );
+        fs::remove_dir_all(&package.directory)?;
+    }
+
+    // Install the package into a staging directory and move it into
+    // place when done, so a failed installation never looks installed.
+    if package.staging_directory.exists() {
+        fs::remove_dir_all(&package.staging_directory)?;
+    }
+    trace!("Creating staging directory...");
+    fs::create_dir(&package.staging_directory)?;
+    let hash = match stage_package(config, package, expected_hash) {
+        Ok(hash) => hash,
+        Err(e) => {
+            fs::remove_dir_all(&package.staging_directory)?;
+            return Err(e);
+        }
+    };
+    fs::rename(&package.staging_directory, &package.directory)?;
+
+    info!("Installed {} ({}).", package.name, package.version);
+    return Ok(hash);
+}
+
+fn stage_package(
+    config: &Config,
+    package: &Package,
+    expected_hash: &Option<String>,
+) -> CakeupResult<Option<String>> {
+    let cake_nupkg_path = package.staging_directory.join(&package.name);
+    fetch_package(config, &package, &cake_nupkg_path)?;
+    let hash = verify_package(&package, &cake_nupkg_path, expected_hash)?;
+
+    trace!("Unzipping {} binaries...", package.name);
+    zip::unzip(&cake_nupkg_path, &package.staging_directory)?;
+    fs::write(package.staging_directory.join(COMPLETE_MARKER), "")?;
+    return Ok(hash);
+}
+
+fn verify_package(
+    package: &Package,
+    path: &Path,
+    expected_hash: &Option<String>,
+) -> CakeupResult<Option<String>> {
+    // Older installations might not have kept the package.
+    if !path.exists() {
+        return Ok(expected_hash.clone());
+    }
+    let hash = Some(hash::sha512(path)?);
+    lockfile::verify_hash(&package.name, expected_hash, &hash)?;
+    return Ok(hash);
 }
 
-fn fetch_package(package: &Package) -> CakeupResult<()> {
-    let path = package.get_path();
-    let home = dirs::home_dir();
-    if home.is_some() {
-        let packages_path = home
-            .unwrap()
-            .join(".nuget")
-            .join("packages")
-            .join(&package.name.to_lowercase())
-            .join(format!("{}", &package.version))
-            .join(&package.filename);
+fn fetch_package(config: &Config, package: &Package, path: &Path) -> CakeupResult<()> {
+    if let Some(global_packages_path) = global_packages::get_path(config)? {
+        let packages_path = global_packages::get_package_directory(
+            &global_packages_path,
+            &package.name,
+            &package.version,
+        )
+        .join(&package.filename);
 
         if packages_path.exists() {
             trace!(
                 "Copying {} package from global package cache...",
                 package.name
             );
-            let bytes_copied = fs::copy(packages_path, &path)?;
+            let bytes_copied = fs::copy(&packages_path, path)?;
             if bytes_copied > 0 {
-                return Ok(());
+                // Verify the package against the hash that NuGet stored.
+                let hash_path =
+                    packages_path.with_file_name(format!("{}.sha512", package.filename));
+                if !hash_path.exists() {
+                    return Ok(());
+                }
+                let expected_hash = fs::read_to_string(&hash_path)?;
+                if hash::verify_sha512(path, &expected_hash[..])? {
+                    return Ok(());
+                }
+                warn!(
+                    "The {} package in the global package cache is corrupt.",
+                    package.name
+                );
+                fs::remove_file(path)?;
             }
         }
     }
 
-    let url = package.get_url();
-    trace!("Downloading {}...", url);
-    let user_agent = &format!("Cakeup NuGet Client/{}", crate::utils::version::VERSION)[..];
-    http::download(&url, &path, Some(user_agent))?;
+    // Is the package in the cakeup download cache?
+    let cache = Cache::new(config);
+    let version = format!("{}", package.version);
+    let key = [
+        "packages",
+        &package.name[..],
+        &version[..],
+        &package.filename[..],
+    ];
+    let restored = match cache {
+        Some(ref cache) => cache.restore(&key, path)?,
+        None => false,
+    };
+    if !restored {
+        download_package(config, package, path)?;
+        if let Some(ref cache) = cache {
+            cache.store(&key, path);
+        }
+    }
 
+    // Share the package with NuGet and other repositories.
+    if config.global_packages {
+        global_packages::add(config, &package.name, &package.version, path);
+    }
     return Ok(());
 }
 
+fn download_package(config: &Config, package: &Package, path: &Path) -> CakeupResult<()> {
+    // Download the package, and give it another chance if the downloaded
+    // file is corrupt. It's downloaded outside of the staging directory,
+    // which is removed when the installation fails, so that a partial
+    // download can be resumed by the next installation.
+    let feeds = feed::open_sources(config)?;
+    for attempt in 1..3 {
+        let downloaded = feed::download_package(
+            &feeds,
+            &package.name,
+            &package.version,
+            &package.download_path,
+        );
+        let expected_hash = match downloaded {
+            Ok(hash) => hash,
+            // Offline, only local package sources are used, and
+            // the package might be available once we're online.
+            Err(_) if config.offline => {
+                return Err(
+                    MissingOffline(format!("{} ({})", package.name, package.version)).into(),
+                )
+            }
+            Err(e) => return Err(e),
+        };
+        fs::rename(&package.download_path, path)?;
+        let expected_hash = match expected_hash {
+            Some(hash) => hash,
+            None => return Ok(()),
+        };
+        if hash::verify_sha512(path, &expected_hash[..])? {
+            return Ok(());
+        }
+
+        fs::remove_file(path)?;
+        if attempt == 1 {
+            warn!(
+                "The downloaded {} package did not match the published hash. Retrying...",
+                package.name
+            );
+        }
+    }
+
+    return Err(format_err!(
+        "The downloaded {} ({}) package did not match the published SHA-512 hash.",
+        package.name,
+        package.version
+    ));
+}
+
 fn get_executable_path(package: &Package) -> PathBuf {
     let filename = if package.core_clr {
         "Cake.dll"
@@ -2,22 +2,93 @@
 // The .NET Foundation licenses this file to you under the MIT license.
 // See the LICENSE file in the project root for more information.
 
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml;
+
+use crate::utils::{CakeupResult, OneOrMany};
+
+pub const CONFIG_FILENAME: &'static str = "cakeup.toml";
 
 pub struct Config {
     pub root: PathBuf,
     pub tools: PathBuf,
     pub cake_version: Option<String>,
     pub nuget_version: Option<String>,
-    pub sdk_version: Option<String>,
+    pub sdk_versions: Vec<String>,
+    pub sdk_policy: String,
+    pub runtimes: Vec<String>,
+    pub sources: Vec<String>,
     pub use_coreclr: bool,
     pub bootstrap: bool,
     pub execute_script: bool,
+    pub locked: bool,
+    pub retries: u32,
+    pub lock_timeout: u64,
+    pub cache: Option<PathBuf>,
+    pub offline: bool,
+    pub global_packages: bool,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+    pub quiet: bool,
+    pub prerelease: bool,
     pub remaining: Vec<String>
 }
 
 impl Config {
     pub fn should_create_tools_directory(&self) -> bool {
-        return self.cake_version != None || self.nuget_version != None || self.sdk_version != None;
+        return self.cake_version != None
+            || self.nuget_version != None
+            || !self.sdk_versions.is_empty();
+    }
+}
+
+/// Settings read from a cakeup.toml file in the project root.
+/// Every setting is optional and is only used when it has not
+/// been provided as an argument or an environment variable.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub cake: Option<String>,
+    pub nuget: Option<String>,
+    pub sdk: Option<OneOrMany<String>>,
+    pub sdk_policy: Option<String>,
+    pub runtimes: Option<Vec<String>>,
+    pub sources: Option<Vec<String>>,
+    pub coreclr: Option<bool>,
+    pub bootstrap: Option<bool>,
+    pub execute: Option<bool>,
+    pub locked: Option<bool>,
+    pub retries: Option<u32>,
+    pub lock_timeout: Option<u32>,
+    pub cache: Option<String>,
+    pub offline: Option<bool>,
+    pub global_packages: Option<bool>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<String>,
+    pub quiet: Option<bool>,
+    pub prerelease: Option<bool>,
+    pub args: Option<Vec<String>>,
+}
+
+impl ConfigFile {
+    pub fn load(root: &Path) -> CakeupResult<Option<ConfigFile>> {
+        let path = root.join(CONFIG_FILENAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        trace!("Reading {}...", path.display());
+        let content = fs::read_to_string(&path)?;
+        return match toml::from_str::<ConfigFile>(&content[..]) {
+            Ok(file) => Ok(Some(file)),
+            Err(e) => Err(format_err!("Could not parse {}. {}", CONFIG_FILENAME, e)),
+        };
     }
-}
\ No newline at end of file
+
+    /// Gets the wanted SDK versions, which can be a single version or a list.
+    pub fn get_sdk_versions(&self) -> Option<Vec<String>> {
+        return self.sdk.clone().map(|sdk| sdk.into_vec());
+    }
+}
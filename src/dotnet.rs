@@ -4,81 +4,550 @@
 
 use std::env;
 use std::fs;
-use std::path::PathBuf;
-use std::process;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
 use std::str;
+use std::thread;
+use std::time::Duration;
 
-use failure;
 use semver::Version;
 
-use crate::Config;
+use crate::global_json::{self, SdkRequirement};
+use crate::lockfile::{self, LockEntry, Lockfile};
+use crate::offline::{self, MissingOffline};
+use crate::releases::{self, Product};
+use crate::utils::cache::Cache;
+use crate::utils::filelock::FileLock;
+use crate::utils::version::VersionSpec;
 use crate::utils::CakeupResult;
-use crate::utils::{http, platform};
+use crate::utils::{hash, http, platform, zip};
+use crate::Config;
+
+/// Where the installation script downloads the SDK from.
+const RELEASES_HOST: &'static str = "https://dotnetcli.azureedge.net/";
+
+/// Decides whether an installed SDK can be used instead of the wanted one.
+#[derive(Clone, Copy)]
+enum SdkPolicy {
+    /// Only the wanted version.
+    Exact,
+    /// The wanted version or a later patch in the same feature band.
+    Feature,
+    /// The wanted version or any later version with the same major version.
+    Major,
+}
 
-pub fn install(config: &Config) -> CakeupResult<()> {
+impl SdkPolicy {
+    fn parse(config: &Config) -> CakeupResult<SdkPolicy> {
+        return match &config.sdk_policy.to_lowercase()[..] {
+            "exact" => Ok(SdkPolicy::Exact),
+            "feature" => Ok(SdkPolicy::Feature),
+            "major" => Ok(SdkPolicy::Major),
+            _ => Err(format_err!(
+                "Provided .NET Core SDK policy '{}' is not valid. Use exact, feature or major.",
+                config.sdk_policy
+            )),
+        };
+    }
+
+    fn accepts(&self, installed: &Version, wanted: &Version) -> bool {
+        return match self {
+            SdkPolicy::Exact => installed == wanted,
+            SdkPolicy::Feature => {
+                installed.major == wanted.major
+                    && installed.minor == wanted.minor
+                    && installed.patch / 100 == wanted.patch / 100
+                    && installed >= wanted
+            }
+            SdkPolicy::Major => installed.major == wanted.major && installed >= wanted,
+        };
+    }
+}
+
+pub fn install(config: &Config, lockfile: &mut Lockfile) -> CakeupResult<()> {
     if !should_install(config) {
         return Ok(());
     }
+    let runtimes = resolve_runtimes(config, lockfile)?;
 
-    // Parse the wanted SDK version.
-    let sdk_version = match Version::parse(&config.sdk_version.as_ref().unwrap()[..]) {
-        Ok(v) => v,
-        Err(_) => {
-            return Err(failure::err_msg(
-                "Provided .NET Core SDK version is not valid.",
-            ))
+    // Runtimes can only be installed locally, so if the global dotnet
+    // is missing any of them, the SDKs are installed locally as well.
+    let global = get_installed_runtimes(None)?;
+    let allow_global = runtimes
+        .iter()
+        .all(|&(_, product, ref version)| get_runtime_versions(&global, product).contains(version));
+
+    install_sdk(config, lockfile, allow_global)?;
+    install_runtimes(config, lockfile, &runtimes)?;
+    return Ok(());
+}
+
+fn install_sdk(config: &Config, lockfile: &mut Lockfile, allow_global: bool) -> CakeupResult<()> {
+    // Without a wanted version, use the SDK required by global.json.
+    if config.sdk_versions.is_empty() {
+        return match global_json::find_sdk_requirement(&config.root)? {
+            Some(requirement) => install_required(config, lockfile, &requirement, allow_global),
+            None => Ok(()),
+        };
+    }
+
+    let policy = SdkPolicy::parse(config)?;
+    let mut wanted = Vec::new();
+    for value in config.sdk_versions.iter() {
+        wanted.push(resolve_wanted(config, lockfile, value)?);
+    }
+
+    // The local installation hides the global SDKs once it's first on PATH,
+    // so if any SDK has to be installed locally, all of them are.
+    let global = get_installed_sdks(None)?;
+    let use_global = allow_global
+        && wanted.iter().all(|version| {
+            find_highest(&global, &|installed| policy.accepts(installed, version)).is_some()
+        });
+
+    // Install every wanted SDK side by side.
+    let mut locked = Vec::new();
+    for version in wanted.iter() {
+        let expected_hash =
+            lockfile::find_locked_hash(&lockfile.sdk, None, &format!("{}", version));
+        let (_, hash) =
+            install_version(config, version, &expected_hash, use_global, &|installed| {
+                policy.accepts(installed, version)
+            })?;
+        locked.push(LockEntry::new(None, &format!("{}", version), hash));
+    }
+
+    // Verify that every wanted SDK is reachable.
+    let installed = get_installed_sdks(None)?;
+    for version in wanted.iter() {
+        if find_highest(&installed, &|v| policy.accepts(v, version)).is_none() {
+            return Err(format_err!(
+                ".NET Core SDK v{} could not be found on PATH after the installation.",
+                version
+            ));
+        }
+    }
+    lockfile.sdk = locked;
+    return Ok(());
+}
+
+/// Resolves the version of a wanted SDK, preferring the locked version.
+fn resolve_wanted(config: &Config, lockfile: &Lockfile, value: &String) -> CakeupResult<Version> {
+    let spec = parse_version_spec(value)?;
+    let locked = lockfile
+        .sdk
+        .iter()
+        .filter_map(|entry| {
+            lockfile::get_locked_version(Some(entry), None, &spec, config.prerelease)
+        })
+        .next();
+    let sdk_version = match locked {
+        Some(version) => {
+            trace!(
+                "Using .NET Core SDK v{} from {}.",
+                version,
+                lockfile::LOCK_FILENAME
+            );
+            version
+        }
+        None => {
+            lockfile::ensure_unlocked(config, ".NET Core SDK")?;
+
+            // Prefer an already installed SDK when using a floating
+            // version, and when we can't look for the latest one.
+            let prefer_installed = match spec {
+                VersionSpec::Range(_) => true,
+                VersionSpec::Latest => config.offline,
+                VersionSpec::Exact(_) => false,
+            };
+            if prefer_installed {
+                if let Some(version) = find_installed_version(config, &spec)? {
+                    return Ok(version);
+                }
+            }
+
+            resolve_version(config, &spec)?
         }
     };
+    return Ok(sdk_version);
+}
 
-    // Check the currently installed global version.
-    let installed_version = get_installed_version(Option::None)?;
-    if installed_version >= sdk_version {
-        // Newer version installed.
-        info!(
-            ".NET Core SDK v{} is already installed globally (wanted v{}).",
-            &installed_version, &sdk_version
-        );
-        return Ok(());
+/// Installs the SDK required by a global.json file unless
+/// an SDK that satisfies its roll forward policy is installed.
+fn install_required(
+    config: &Config,
+    lockfile: &mut Lockfile,
+    requirement: &SdkRequirement,
+    use_global: bool,
+) -> CakeupResult<()> {
+    info!(
+        "Using .NET Core SDK v{} from {}.",
+        requirement,
+        requirement.path.display()
+    );
+    let satisfies = |version: &Version| requirement.find_best(&vec![version.clone()]).is_some();
+
+    // Prefer the locked version if it satisfies the requirement.
+    let locked = lockfile
+        .sdk
+        .iter()
+        .filter_map(|entry| Version::parse(&entry.version[..]).ok())
+        .find(|v| satisfies(v));
+    let sdk_version = match locked {
+        Some(version) => {
+            trace!(
+                "Using .NET Core SDK v{} from {}.",
+                version,
+                lockfile::LOCK_FILENAME
+            );
+            version
+        }
+        None => requirement.version.clone(),
+    };
+
+    let expected_hash =
+        lockfile::find_locked_hash(&lockfile.sdk, None, &format!("{}", sdk_version));
+    let (version, hash) =
+        install_version(config, &sdk_version, &expected_hash, use_global, &satisfies)?;
+    lockfile.sdk = vec![LockEntry::new(None, &format!("{}", version), hash)];
+    return Ok(());
+}
+
+/// Installs an SDK version into the local installation directory unless an
+/// SDK accepted by the predicate is already installed (globally, if allowed).
+/// Returns the SDK in use and the hash of its archive (or the expected hash
+/// if nothing was downloaded).
+fn install_version(
+    config: &Config,
+    sdk_version: &Version,
+    expected_hash: &Option<String>,
+    use_global: bool,
+    is_satisfied: &dyn Fn(&Version) -> bool,
+) -> CakeupResult<(Version, Option<String>)> {
+    // Check the SDKs that are installed globally.
+    if use_global {
+        if let Some(installed_version) = find_highest(&get_installed_sdks(None)?, is_satisfied) {
+            info!(
+                ".NET Core SDK v{} is already installed globally (wanted v{}).",
+                &installed_version, &sdk_version
+            );
+            return Ok((installed_version, expected_hash.clone()));
+        }
     }
 
-    // Check the currently installed local version.
-    let dotnet_path = get_local_installation_path(config)?;
-    let installed_version = get_installed_version(Option::Some(&dotnet_path))?;
-    if installed_version >= sdk_version {
-        // Newer version installed.
+    // Make sure that the install directory exists, and that
+    // no one else is installing into it while we're using it.
+    let dotnet_path = create_install_directory(&config)?;
+    let _lock = lock_install_directory(config)?;
+
+    // Check the SDKs that are installed locally.
+    let installed = get_installed_sdks(Some(&dotnet_path))?;
+    for version in installed.iter() {
+        trace!(
+            "Found .NET Core SDK v{} in {}.",
+            version,
+            dotnet_path.display()
+        );
+    }
+    if let Some(installed_version) = find_highest(&installed, is_satisfied) {
         set_environment_variables(&dotnet_path)?;
         info!(
             ".NET Core SDK v{} is already installed locally (wanted v{}).",
             &installed_version, &sdk_version
         );
-        return Ok(());
+        return Ok((installed_version, expected_hash.clone()));
     }
 
-    // Make sure that the install directory exists.
-    let dotnet_path = create_install_directory(&config)?;
-
-    // Execute the installation script.
-    execute_install_script(&dotnet_path, &sdk_version)?;
+    // Install the SDK.
+    let hash = install_archive(
+        config,
+        &dotnet_path,
+        Product::Sdk,
+        &sdk_version,
+        expected_hash,
+    )?;
     set_environment_variables(&dotnet_path)?;
 
-    // Get the installed version again and verify that it's reachable.
+    // Verify that the installed SDK is reachable.
     info!("Verifying installation...");
-    let installed_version = get_installed_version(Option::None)?;
-    if installed_version < sdk_version {
+    if !get_installed_sdks(None)?.contains(sdk_version) {
         return Err(format_err!(
-            "Found v{} on PATH but wanted v{}.",
-            &installed_version,
+            "Installed .NET Core SDK v{} but it could not be found on PATH.",
             &sdk_version
         ));
-    } else {
-        info!("Dotnet SDK v{} has been installed.", &installed_version);
     }
+    info!("Dotnet SDK v{} has been installed.", &sdk_version);
+
+    return Ok((sdk_version.clone(), hash));
+}
+
+/// Resolves the versions of the shared runtimes wanted in addition to the SDK.
+fn resolve_runtimes<'a>(
+    config: &'a Config,
+    lockfile: &Lockfile,
+) -> CakeupResult<Vec<(&'a str, Product, Version)>> {
+    let mut runtimes = Vec::new();
+    for value in config.runtimes.iter() {
+        let (name, product, spec) = parse_runtime(value)?;
+        let version = match find_locked_runtime(config, lockfile, name, &spec) {
+            Some(version) => {
+                trace!(
+                    "Using {} v{} from {}.",
+                    product.get_name(),
+                    version,
+                    lockfile::LOCK_FILENAME
+                );
+                version
+            }
+            None => {
+                lockfile::ensure_unlocked(config, product.get_name())?;
+                resolve_runtime_version(config, product, &spec)?
+            }
+        };
+        runtimes.push((name, product, version));
+    }
+    return Ok(runtimes);
+}
 
+/// Installs the resolved runtimes and locks their versions.
+fn install_runtimes(
+    config: &Config,
+    lockfile: &mut Lockfile,
+    runtimes: &Vec<(&str, Product, Version)>,
+) -> CakeupResult<()> {
+    let mut locked = Vec::new();
+    for &(name, product, ref version) in runtimes.iter() {
+        let version_string = format!("{}", version);
+        let expected_hash =
+            lockfile::find_locked_hash(&lockfile.runtimes, Some(name), &version_string);
+        let hash = install_runtime(config, product, version, &expected_hash)?;
+        locked.push(LockEntry::new(Some(name), &version_string, hash));
+    }
+    lockfile.runtimes = locked;
     return Ok(());
 }
 
+/// Parses a wanted runtime such as dotnet:2.1.30 or aspnetcore:3.1.x.
+fn parse_runtime(value: &str) -> CakeupResult<(&str, Product, VersionSpec)> {
+    let mut parts = value.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let product = match name {
+        "dotnet" => Product::Runtime,
+        "aspnetcore" => Product::AspNetCoreRuntime,
+        _ => {
+            return Err(format_err!(
+                "Provided runtime '{}' is not valid. Use dotnet:<version> or aspnetcore:<version>.",
+                value
+            ))
+        }
+    };
+    return match parts.next().map(|version| VersionSpec::parse(version)) {
+        Some(Ok(spec)) => Ok((name, product, spec)),
+        _ => Err(format_err!(
+            "Provided {} version in '{}' is not valid.",
+            product.get_name(),
+            value
+        )),
+    };
+}
+
+fn find_locked_runtime(
+    config: &Config,
+    lockfile: &Lockfile,
+    name: &str,
+    spec: &VersionSpec,
+) -> Option<Version> {
+    return lockfile
+        .runtimes
+        .iter()
+        .filter_map(|entry| {
+            lockfile::get_locked_version(Some(entry), Some(name), spec, config.prerelease)
+        })
+        .next();
+}
+
+fn resolve_runtime_version(
+    config: &Config,
+    product: Product,
+    spec: &VersionSpec,
+) -> CakeupResult<Version> {
+    // Prefer an already installed runtime when using a floating
+    // version, and when we can't look for the latest one.
+    let prefer_installed = match spec {
+        VersionSpec::Range(_) => true,
+        VersionSpec::Latest => config.offline,
+        VersionSpec::Exact(ref version) => return Ok(version.clone()),
+    };
+    if prefer_installed {
+        let mut installed = get_runtime_versions(&get_installed_runtimes(None)?, product);
+        let dotnet_path = get_local_installation_path(config)?;
+        installed.extend(get_runtime_versions(
+            &get_installed_runtimes(Some(&dotnet_path))?,
+            product,
+        ));
+        if let Some(version) = spec.find_best(&installed, config.prerelease) {
+            info!(
+                "Using installed {} v{} matching {}.",
+                product.get_name(),
+                version,
+                spec
+            );
+            return Ok(version);
+        }
+    }
+    if config.offline {
+        return Err(MissingOffline(format!("{} ({})", product.get_name(), spec)).into());
+    }
+
+    info!(
+        "Resolving version of the {} matching {}...",
+        product.get_name(),
+        spec
+    );
+    return match releases::find_version(config, product, spec)? {
+        Some(version) => {
+            info!("Resolved {} {} to {}.", product.get_name(), spec, version);
+            Ok(version)
+        }
+        None => Err(format_err!(
+            "Could not find a {} version matching {}.",
+            product.get_name(),
+            spec
+        )),
+    };
+}
+
+/// Installs a runtime into the local installation directory unless it's
+/// already installed for the dotnet on PATH. Returns the hash of its archive
+/// (or the expected hash if nothing was downloaded).
+fn install_runtime(
+    config: &Config,
+    product: Product,
+    version: &Version,
+    expected_hash: &Option<String>,
+) -> CakeupResult<Option<String>> {
+    if get_runtime_versions(&get_installed_runtimes(None)?, product).contains(version) {
+        info!("{} v{} is already installed.", product.get_name(), version);
+        return Ok(expected_hash.clone());
+    }
+
+    // A runtime installed locally can only be used by the local dotnet, which
+    // is only missing from PATH here if no SDK was installed along with it.
+    let dotnet_path = create_install_directory(&config)?;
+    if !is_local_installation_used(&dotnet_path) {
+        return Err(format_err!(
+            "{} v{} is not installed for the dotnet on PATH. Install it globally, \
+             or use --sdk to install a .NET Core SDK alongside it.",
+            product.get_name(),
+            version
+        ));
+    }
+
+    let _lock = lock_install_directory(config)?;
+    let installed = get_runtime_versions(&get_installed_runtimes(Some(&dotnet_path))?, product)
+        .contains(version);
+    let hash = if installed {
+        expected_hash.clone()
+    } else {
+        install_archive(config, &dotnet_path, product, version, expected_hash)?
+    };
+    set_environment_variables(&dotnet_path)?;
+
+    // Verify that the runtime can be used by the dotnet on PATH.
+    info!("Verifying installation...");
+    if !get_runtime_versions(&get_installed_runtimes(None)?, product).contains(version) {
+        return Err(format_err!(
+            "Installed {} v{} but it could not be found with dotnet --list-runtimes.",
+            product.get_name(),
+            version
+        ));
+    }
+    if installed {
+        info!(
+            "{} v{} is already installed locally.",
+            product.get_name(),
+            version
+        );
+    } else {
+        info!("{} v{} has been installed.", product.get_name(), version);
+    }
+    return Ok(hash);
+}
+
+/// The SDK is installed if a version is wanted, or if there's a
+/// global.json file that might require one. Runtimes are installed
+/// alongside it if any are wanted.
 pub fn should_install(config: &Config) -> bool {
-    return config.sdk_version.is_some();
+    return !config.sdk_versions.is_empty()
+        || global_json::find_path(&config.root).is_some()
+        || !config.runtimes.is_empty();
+}
+
+fn parse_version_spec(value: &String) -> CakeupResult<VersionSpec> {
+    return match VersionSpec::parse(&value[..]) {
+        Ok(spec) => Ok(spec),
+        Err(_) => Err(format_err!(
+            "Provided .NET Core SDK version '{}' is not valid.",
+            value
+        )),
+    };
+}
+
+fn resolve_version(config: &Config, spec: &VersionSpec) -> CakeupResult<Version> {
+    return match spec {
+        VersionSpec::Exact(ref version) => Ok(version.clone()),
+        _ if config.offline => Err(MissingOffline(format!(".NET Core SDK ({})", spec)).into()),
+        VersionSpec::Latest => {
+            info!("Resolving latest version of the .NET Core SDK...");
+            let version = releases::get_latest_sdk_version(config)?;
+            info!(
+                "Resolved latest version of the .NET Core SDK to {}.",
+                version
+            );
+            Ok(version)
+        }
+        VersionSpec::Range(_) => {
+            info!(
+                "Resolving version of the .NET Core SDK matching {}...",
+                spec
+            );
+            match releases::find_version(config, Product::Sdk, spec)? {
+                Some(version) => {
+                    info!("Resolved .NET Core SDK {} to {}.", spec, version);
+                    Ok(version)
+                }
+                None => Err(format_err!(
+                    "Could not find a .NET Core SDK version matching {}.",
+                    spec
+                )),
+            }
+        }
+    };
+}
+
+fn find_installed_version(config: &Config, spec: &VersionSpec) -> CakeupResult<Option<Version>> {
+    if let Some(installed_version) = spec.find_best(&get_installed_sdks(None)?, config.prerelease) {
+        info!(
+            ".NET Core SDK v{} is already installed globally (wanted {}).",
+            &installed_version, spec
+        );
+        return Ok(Some(installed_version));
+    }
+
+    let dotnet_path = get_local_installation_path(config)?;
+    if let Some(installed_version) =
+        spec.find_best(&get_installed_sdks(Some(&dotnet_path))?, config.prerelease)
+    {
+        set_environment_variables(&dotnet_path)?;
+        info!(
+            ".NET Core SDK v{} is already installed locally (wanted {}).",
+            &installed_version, spec
+        );
+        return Ok(Some(installed_version));
+    }
+
+    return Ok(None);
 }
 
 fn get_local_installation_path(config: &Config) -> CakeupResult<PathBuf> {
@@ -88,32 +557,185 @@ fn get_local_installation_path(config: &Config) -> CakeupResult<PathBuf> {
 }
 
 fn create_install_directory(config: &Config) -> CakeupResult<PathBuf> {
-    let dotnet_path = config.root.join(".dotnet");
+    let dotnet_path = get_local_installation_path(config)?;
     if !dotnet_path.exists() {
-        fs::create_dir(&dotnet_path)?;
+        fs::create_dir_all(&dotnet_path)?;
     }
+    return Ok(dotnet_path);
+}
+
+fn lock_install_directory(config: &Config) -> CakeupResult<FileLock> {
     let platform = platform::get_platform_name()?;
-    let dotnet_path = dotnet_path.join(platform);
-    if !dotnet_path.exists() {
-        fs::create_dir(&dotnet_path)?;
+    let path = config
+        .root
+        .join(".dotnet")
+        .join(format!(".{}.lock", platform));
+    return FileLock::acquire(&path, Duration::from_secs(config.lock_timeout));
+}
+
+/// Gets all SDKs installed globally (the dotnet on PATH)
+/// or in the provided installation directory.
+fn get_installed_sdks(path: Option<&PathBuf>) -> CakeupResult<Vec<Version>> {
+    let mut command = match path {
+        None => process::Command::new("dotnet"),
+        Some(path) => process::Command::new(path.join("dotnet")),
+    };
+
+    // Each line looks like "2.1.302 [/usr/share/dotnet/sdk]".
+    command.arg("--list-sdks");
+    if let Some(output) = execute_and_return_output(&mut command) {
+        let mut versions = Vec::new();
+        for line in output.lines().filter(|l| !l.trim().is_empty()) {
+            let version = line.split_whitespace().next().unwrap_or("");
+            match Version::parse(version) {
+                Ok(version) => versions.push(version),
+                Err(e) => {
+                    return Err(format_err!(
+                        "Could not parse the .NET Core SDK version '{}' reported by dotnet. {}",
+                        line.trim(),
+                        e
+                    ))
+                }
+            };
+        }
+        return Ok(versions);
     }
-    return Ok(dotnet_path);
+
+    // Older versions of dotnet can't list SDKs, so
+    // look in the sdk directory of the installation.
+    let root = match path {
+        Some(path) => Some(path.clone()),
+        None => find_dotnet_root(),
+    };
+    return match root {
+        Some(root) => read_sdk_directory(&root.join("sdk")),
+        None => Ok(Vec::new()),
+    };
 }
 
-fn get_installed_version(path: Option<&PathBuf>) -> CakeupResult<Version> {
+/// Gets all runtimes (such as Microsoft.NETCore.App 2.1.30) installed
+/// globally (the dotnet on PATH) or in the provided installation directory.
+fn get_installed_runtimes(path: Option<&PathBuf>) -> CakeupResult<Vec<(String, Version)>> {
     let mut command = match path {
         None => process::Command::new("dotnet"),
         Some(path) => process::Command::new(path.join("dotnet")),
     };
 
-    // Get the currently installed dotnet version.
-    command.arg("--version");
-    let version = match execute_and_return_output(&mut command) {
-        Some(v) => v,
-        None => String::from("0.0.0"),
+    // Each line looks like "Microsoft.NETCore.App 2.1.30 [/usr/share/dotnet/shared/Microsoft.NETCore.App]".
+    command.arg("--list-runtimes");
+    if let Some(output) = execute_and_return_output(&mut command) {
+        let mut runtimes = Vec::new();
+        for line in output.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.split_whitespace();
+            let name = parts.next().unwrap_or("");
+            match Version::parse(parts.next().unwrap_or("")) {
+                Ok(version) => runtimes.push((name.to_string(), version)),
+                Err(e) => {
+                    return Err(format_err!(
+                        "Could not parse the runtime '{}' reported by dotnet. {}",
+                        line.trim(),
+                        e
+                    ))
+                }
+            };
+        }
+        return Ok(runtimes);
+    }
+
+    // Older versions of dotnet can't list runtimes, so
+    // look in the shared directory of the installation.
+    let root = match path {
+        Some(path) => Some(path.clone()),
+        None => find_dotnet_root(),
+    };
+    return match root {
+        Some(root) => read_shared_directory(&root.join("shared")),
+        None => Ok(Vec::new()),
+    };
+}
+
+fn read_shared_directory(path: &Path) -> CakeupResult<Vec<(String, Version)>> {
+    let mut runtimes = Vec::new();
+    if !path.is_dir() {
+        return Ok(runtimes);
+    }
+    for framework in fs::read_dir(path)? {
+        let framework = framework?;
+        if !framework.path().is_dir() {
+            continue;
+        }
+        let name = framework.file_name().to_string_lossy().to_string();
+        for entry in fs::read_dir(framework.path())? {
+            let entry = entry?;
+            if let Ok(version) = Version::parse(&entry.file_name().to_string_lossy()[..]) {
+                runtimes.push((name.clone(), version));
+            }
+        }
+    }
+    return Ok(runtimes);
+}
+
+/// Gets the installed versions of a runtime.
+fn get_runtime_versions(runtimes: &Vec<(String, Version)>, product: Product) -> Vec<Version> {
+    let framework = match product {
+        Product::Sdk => return Vec::new(),
+        Product::Runtime => "Microsoft.NETCore.App",
+        Product::AspNetCoreRuntime => "Microsoft.AspNetCore.App",
+    };
+    return runtimes
+        .iter()
+        .filter(|&&(ref name, _)| name == framework)
+        .map(|&(_, ref version)| version.clone())
+        .collect();
+}
+
+fn read_sdk_directory(path: &Path) -> CakeupResult<Vec<Version>> {
+    let mut versions = Vec::new();
+    if !path.is_dir() {
+        return Ok(versions);
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.path().join("dotnet.dll").exists() {
+            continue;
+        }
+        if let Ok(version) = Version::parse(&entry.file_name().to_string_lossy()[..]) {
+            versions.push(version);
+        }
+    }
+    return Ok(versions);
+}
+
+/// Finds the installation directory of the dotnet on PATH.
+fn find_dotnet_root() -> Option<PathBuf> {
+    let filename = if cfg!(windows) {
+        "dotnet.exe"
+    } else {
+        "dotnet"
+    };
+    let path = env::var_os("PATH")?;
+    return env::split_paths(&path)
+        .map(|directory| directory.join(filename))
+        .find(|executable| executable.is_file())
+        .and_then(|executable| fs::canonicalize(executable).ok())
+        .and_then(|executable| executable.parent().map(|p| p.to_path_buf()));
+}
+
+/// Checks whether the dotnet on PATH is the one in the installation
+/// directory, which is also the case when there is no dotnet on PATH.
+fn is_local_installation_used(dotnet_path: &Path) -> bool {
+    return match find_dotnet_root() {
+        Some(root) => fs::canonicalize(dotnet_path).ok() == Some(root),
+        None => true,
     };
+}
 
-    return Ok(Version::parse(&version[..]).unwrap());
+fn find_highest(versions: &Vec<Version>, predicate: &dyn Fn(&Version) -> bool) -> Option<Version> {
+    return versions
+        .iter()
+        .filter(|v| predicate(v))
+        .max()
+        .map(|v| v.clone());
 }
 
 fn execute_and_return_output(command: &mut process::Command) -> Option<String> {
@@ -132,8 +754,12 @@ fn execute_and_return_output(command: &mut process::Command) -> Option<String> {
 }
 
 fn set_environment_variables(dotnet_path: &PathBuf) -> CakeupResult<()> {
-    // Update the environment path.
-    let env_path = &env::var("PATH").unwrap()[..];
+    // Update the environment path, unless the SDK and the
+    // runtimes have already put the installation first.
+    let env_path = env::var("PATH").unwrap_or_default();
+    if env::split_paths(&env_path).next().as_ref() == Some(dotnet_path) {
+        return Ok(());
+    }
     env::set_var(
         "PATH",
         format!(
@@ -158,20 +784,207 @@ fn get_path_separator() -> String {
     return String::from(";");
 }
 
-fn execute_install_script(dotnet_path: &PathBuf, version: &Version) -> CakeupResult<()> {
+/// Installs an SDK or a runtime from the archive published in the release
+/// metadata, falling back to the installation script if there is no such archive.
+/// Returns the hash of the archive, which must match the expected hash (if any).
+fn install_archive(
+    config: &Config,
+    dotnet_path: &PathBuf,
+    product: Product,
+    version: &Version,
+    expected_hash: &Option<String>,
+) -> CakeupResult<Option<String>> {
+    let rid = platform::get_runtime_identifier()?;
+    let extension = if platform::is_windows()? {
+        ".zip"
+    } else {
+        ".tar.gz"
+    };
+    let filename = format!(
+        "{}-{}-{}{}",
+        product.get_file_prefix(),
+        version,
+        rid,
+        extension
+    );
+    let archive = dotnet_path.join(&filename);
+
+    // Is the archive in the download cache?
+    let cache = Cache::new(config);
+    let version_string = format!("{}", version);
+    let key = [
+        "dotnet",
+        product.get_file_prefix(),
+        &version_string[..],
+        &filename[..],
+    ];
+    let restored = match cache {
+        Some(ref cache) => cache.restore(&key, &archive)?,
+        None => false,
+    };
+
+    if !restored {
+        offline::ensure_online(config, format!("{} v{}", product.get_name(), version))?;
+        let file = match releases::find_file(config, product, version, &rid, extension)? {
+            Some(file) => file,
+            None => {
+                warn!(
+                    "Could not find a {} v{} archive for {}. Using installation script...",
+                    product.get_name(),
+                    version,
+                    rid
+                );
+                execute_install_script(config, dotnet_path, product, version)?;
+                return Ok(None);
+            }
+        };
+
+        info!("Downloading {} v{}...", product.get_name(), version);
+        http::Client::new(config).download(&file.url, &archive)?;
+        if file.hash.is_empty() {
+            warn!("No hash has been published for {}.", file.name);
+        } else if !hash::verify_sha512_hex(&archive, &file.hash[..])? {
+            fs::remove_file(&archive)?;
+            return Err(format_err!(
+                "The downloaded {} v{} did not match the published SHA-512 hash.",
+                product.get_name(),
+                version
+            ));
+        }
+        if let Some(ref cache) = cache {
+            cache.store(&key, &archive);
+        }
+    }
+
+    // Make sure that we're installing the same archive as before.
+    let hash = Some(hash::sha512(&archive)?);
+    let name = format!("{} v{}", product.get_name(), version);
+    if let Err(e) = lockfile::verify_hash(&name, expected_hash, &hash) {
+        fs::remove_file(&archive)?;
+        return Err(e);
+    }
+
+    info!("Installing {}...", name);
+    let result = extract_archive(&archive, dotnet_path);
+    fs::remove_file(&archive)?;
+    result?;
+    return Ok(hash);
+}
+
+/// Extracts an archive into the installation directory without replacing files
+/// that are already there, so that side by side installations don't clobber
+/// each other (like the installation script's --skip-non-versioned-files).
+fn extract_archive(archive: &Path, dotnet_path: &Path) -> CakeupResult<()> {
+    let temp_path = dotnet_path.join(".extract");
+    if temp_path.exists() {
+        fs::remove_dir_all(&temp_path)?;
+    }
+    fs::create_dir_all(&temp_path)?;
+    let result = match unpack_archive(archive, &temp_path) {
+        Ok(()) => merge_directory(&temp_path, dotnet_path),
+        Err(e) => Err(e),
+    };
+    fs::remove_dir_all(&temp_path)?;
+    return result;
+}
+
+/// Moves everything in the source directory into the target
+/// directory, keeping the files that already exist there.
+fn merge_directory(source: &Path, target: &Path) -> CakeupResult<()> {
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let destination = target.join(entry.file_name());
+        if fs::symlink_metadata(&destination).is_err() {
+            fs::rename(entry.path(), &destination)?;
+        } else if entry.file_type()?.is_dir() && destination.is_dir() {
+            merge_directory(&entry.path(), &destination)?;
+        } else {
+            trace!("Keeping existing {}.", destination.display());
+        }
+    }
+    return Ok(());
+}
+
+fn unpack_archive(archive: &Path, dotnet_path: &Path) -> CakeupResult<()> {
+    if archive.to_string_lossy().ends_with(".zip") {
+        return zip::unzip(archive, dotnet_path);
+    }
+
+    let output = process::Command::new("tar")
+        .arg("-xzf")
+        .arg(archive)
+        .arg("-C")
+        .arg(dotnet_path)
+        .output()?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "Could not extract {}. {}",
+            archive.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    return Ok(());
+}
+
+fn execute_install_script(
+    config: &Config,
+    dotnet_path: &PathBuf,
+    product: Product,
+    version: &Version,
+) -> CakeupResult<()> {
     if platform::is_windows()? {
-        return execute_powershell_install_script(dotnet_path, version);
+        return execute_powershell_install_script(config, dotnet_path, product, version);
     } else {
-        return execute_bash_install_script(dotnet_path, version);
+        return execute_bash_install_script(config, dotnet_path, product, version);
     }
 }
 
-fn execute_bash_install_script(dotnet_path: &PathBuf, version: &Version) -> CakeupResult<()> {
+/// Gets the value of the installation script's runtime
+/// parameter, which installs a runtime instead of the SDK.
+fn get_script_runtime(product: Product) -> Option<&'static str> {
+    return match product {
+        Product::Sdk => None,
+        Product::Runtime => Some("dotnet"),
+        Product::AspNetCoreRuntime => Some("aspnetcore"),
+    };
+}
+
+fn download_install_script(config: &Config, filename: &str, path: &Path) -> CakeupResult<()> {
+    let url = format!("https://dot.net/v1/{}", filename);
+    let cache = Cache::new(config);
+    let key = ["dotnet", filename];
+
+    info!("Downloading {}...", url);
+    let error = match http::Client::new(config).download(&url, path) {
+        Ok(()) => {
+            if let Some(ref cache) = cache {
+                cache.store(&key, path);
+            }
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+
+    // The script is updated every now and then, so the
+    // cached script is only used if it can't be downloaded.
+    if let Some(ref cache) = cache {
+        if cache.restore(&key, path)? {
+            warn!("Could not download {}. Using cached script. {}", url, error);
+            return Ok(());
+        }
+    }
+    return Err(error);
+}
+
+fn execute_bash_install_script(
+    config: &Config,
+    dotnet_path: &PathBuf,
+    product: Product,
+    version: &Version,
+) -> CakeupResult<()> {
     // Download the installation script.
     let dotnet_script = dotnet_path.join("dotnet-install.sh");
-    let dotnet_url = String::from("https://dot.net/v1/dotnet-install.sh");
-    info!("Downloading https://dot.net/v1/dotnet-install.sh...");
-    http::download(&dotnet_url, &dotnet_script, None)?;
+    download_install_script(config, "dotnet-install.sh", &dotnet_script)?;
 
     // Give the script executable permissions.
     process::Command::new("chmod")
@@ -180,34 +993,66 @@ fn execute_bash_install_script(dotnet_path: &PathBuf, version: &Version) -> Cake
         .output()?;
 
     // Convert the version to a string.
-    let version = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    let version = version.to_string();
 
     // Execute the script.
-    info!("Installing .NET Core SDK v{}...", version);
-    process::Command::new(&dotnet_script)
+    info!("Installing {} v{}...", product.get_name(), version);
+    let mut command = process::Command::new(&dotnet_script);
+    set_script_proxy_environment(config, &mut command);
+    command
         .arg("--version")
         .arg(version)
         .arg("--install-dir")
         .arg(&dotnet_path)
-        .arg("--no-path")
-        .output()?;
+        .arg("--no-path");
+    if let Some(runtime) = get_script_runtime(product) {
+        command.arg("--runtime").arg(runtime);
+    }
+    if has_dotnet_executable(dotnet_path) {
+        command.arg("--skip-non-versioned-files");
+    }
+    return run_install_script(command, product);
+}
 
-    return Ok(());
+/// Checks whether something has already been installed into the directory,
+/// in which case the script must not replace files shared by all installations.
+fn has_dotnet_executable(dotnet_path: &Path) -> bool {
+    let filename = if cfg!(windows) {
+        "dotnet.exe"
+    } else {
+        "dotnet"
+    };
+    return dotnet_path.join(filename).exists();
 }
 
-fn execute_powershell_install_script(dotnet_path: &PathBuf, version: &Version) -> CakeupResult<()> {
+/// Makes the installation script download through the same
+/// proxy and with the same CA bundle as cakeup itself.
+fn set_script_proxy_environment(config: &Config, command: &mut process::Command) {
+    if let Some(proxy) = http::get_proxy(&config.proxy, RELEASES_HOST) {
+        command.env("https_proxy", &proxy).env("http_proxy", &proxy);
+    }
+    if let Some(ref ca_bundle) = config.ca_bundle {
+        command.env("CURL_CA_BUNDLE", ca_bundle);
+    }
+}
+
+fn execute_powershell_install_script(
+    config: &Config,
+    dotnet_path: &PathBuf,
+    product: Product,
+    version: &Version,
+) -> CakeupResult<()> {
     // Download the installation script.
     let dotnet_script = dotnet_path.join("dotnet-install.ps1");
-    let dotnet_url = String::from("https://dot.net/v1/dotnet-install.ps1");
-    info!("Downloading https://dot.net/v1/dotnet-install.ps1...");
-    http::download(&dotnet_url, &dotnet_script, None)?;
+    download_install_script(config, "dotnet-install.ps1", &dotnet_script)?;
 
     // Convert the version to a string.
-    let version = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    let version = version.to_string();
 
     // Execute the script.
-    info!("Installing .NET Core SDK v{}...", version);
-    process::Command::new("powershell")
+    info!("Installing {} v{}...", product.get_name(), version);
+    let mut command = process::Command::new("powershell");
+    command
         .arg("-NoProfile")
         .arg("-File")
         .arg(dotnet_script)
@@ -216,8 +1061,117 @@ fn execute_powershell_install_script(dotnet_path: &PathBuf, version: &Version) -
         .arg("-Version")
         .arg(version)
         .arg("-InstallDir")
-        .arg(&dotnet_path)
-        .output()?;
+        .arg(&dotnet_path);
+    if let Some(proxy) = http::get_proxy(&config.proxy, RELEASES_HOST) {
+        command.arg("-ProxyAddress").arg(proxy);
+    }
+    if let Some(ref ca_bundle) = config.ca_bundle {
+        // PowerShell only trusts the Windows certificate store.
+        warn!(
+            "The installation script does not use the CA bundle {}.",
+            ca_bundle.display()
+        );
+    }
+    if let Some(runtime) = get_script_runtime(product) {
+        command.arg("-Runtime").arg(runtime);
+    }
+    if has_dotnet_executable(dotnet_path) {
+        command.arg("-SkipNonVersionedFiles");
+    }
+    return run_install_script(command, product);
+}
 
-    return Ok(());
+/// Runs an installation script, writing its output to the trace log,
+/// and fails with the script's own error message if it fails.
+fn run_install_script(mut command: process::Command, product: Product) -> CakeupResult<()> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Read the error output on another thread so that
+    // the script never blocks on a full pipe.
+    let stderr = child.stderr.take().unwrap();
+    let errors = thread::spawn(move || read_script_output(stderr));
+    let output = read_script_output(child.stdout.take().unwrap());
+    let status = child.wait()?;
+    let errors = errors.join().unwrap_or_default();
+    if status.success() {
+        return Ok(());
+    }
+
+    // Use the last lines of the output if the script didn't report any errors.
+    let message = if errors.is_empty() {
+        output
+            .iter()
+            .rev()
+            .take(5)
+            .rev()
+            .cloned()
+            .collect::<Vec<_>>()
+    } else {
+        errors
+    };
+    let code = match status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => String::from("terminated by signal"),
+    };
+    return Err(format_err!(
+        "The {} installation script failed ({}).\n{}",
+        product.get_name(),
+        code,
+        message.join("\n")
+    ));
+}
+
+fn read_script_output<R: Read>(output: R) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in BufReader::new(output).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        trace!("{}", line);
+        if !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    return lines;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepts(policy: SdkPolicy, installed: &str, wanted: &str) -> bool {
+        return policy.accepts(
+            &Version::parse(installed).unwrap(),
+            &Version::parse(wanted).unwrap(),
+        );
+    }
+
+    #[test]
+    fn exact_policy_only_accepts_the_wanted_version() {
+        assert!(accepts(SdkPolicy::Exact, "3.1.100", "3.1.100"));
+        assert!(!accepts(SdkPolicy::Exact, "3.1.101", "3.1.100"));
+    }
+
+    #[test]
+    fn feature_policy_accepts_later_patches_in_the_same_band() {
+        assert!(accepts(SdkPolicy::Feature, "3.1.100", "3.1.100"));
+        assert!(accepts(SdkPolicy::Feature, "3.1.199", "3.1.100"));
+        assert!(!accepts(SdkPolicy::Feature, "3.1.200", "3.1.100"));
+        assert!(!accepts(SdkPolicy::Feature, "3.1.200", "3.1.199"));
+        assert!(!accepts(SdkPolicy::Feature, "3.1.100", "3.1.199"));
+        assert!(!accepts(SdkPolicy::Feature, "5.0.100", "3.1.100"));
+    }
+
+    #[test]
+    fn major_policy_accepts_later_versions_with_the_same_major_version() {
+        assert!(accepts(SdkPolicy::Major, "3.1.200", "3.1.100"));
+        assert!(accepts(SdkPolicy::Major, "3.1.402", "3.0.100"));
+        assert!(!accepts(SdkPolicy::Major, "3.0.100", "3.1.100"));
+        assert!(!accepts(SdkPolicy::Major, "5.0.100", "3.1.100"));
+        assert!(!accepts(SdkPolicy::Major, "3.1.100", "5.0.100"));
+    }
 }
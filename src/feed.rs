@@ -0,0 +1,651 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use roxmltree;
+use semver::Version;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use crate::nuget_config::{self, PackageSource};
+use crate::utils::http::{self, Authorization, HttpError};
+use crate::utils::{version, CakeupResult};
+use crate::Config;
+
+pub const DEFAULT_SOURCE: &'static str = "https://api.nuget.org/v3/index.json";
+
+#[derive(Deserialize)]
+struct ServiceIndex {
+    resources: Vec<Resource>,
+}
+
+#[derive(Deserialize)]
+struct Resource {
+    #[serde(rename = "@id")]
+    id: String,
+    #[serde(rename = "@type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct VersionIndex {
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RegistrationLeaf {
+    #[serde(rename = "catalogEntry")]
+    catalog_entry: String,
+}
+
+#[derive(Deserialize)]
+struct CatalogEntry {
+    #[serde(rename = "packageHash")]
+    package_hash: String,
+    #[serde(rename = "packageHashAlgorithm")]
+    package_hash_algorithm: String,
+}
+
+/// A NuGet package source.
+pub struct Feed {
+    pub url: String,
+    client: http::Client,
+    kind: FeedKind,
+}
+
+enum FeedKind {
+    /// A NuGet v2 (OData) feed.
+    V2,
+    /// A NuGet v3 feed described by a service index.
+    V3 {
+        package_base_address: String,
+        registrations_base_url: Option<String>,
+    },
+    /// A local directory (or file share) containing packages, either
+    /// directly or in the hierarchical <id>/<version> layout.
+    Local { path: PathBuf },
+}
+
+impl Feed {
+    pub fn open(config: &Config, source: &PackageSource) -> CakeupResult<Feed> {
+        let url = source.url.trim_end_matches('/').to_string();
+        let client = http::Client::new(config)
+            .with_user_agent(&get_user_agent()[..])
+            .with_authorization(source.credentials.clone().map(Authorization::Basic));
+        if !is_remote(&url) {
+            let path = config.root.join(get_local_path(&url));
+            if !path.is_dir() {
+                return Err(format_err!(
+                    "The directory {} does not exist.",
+                    path.display()
+                ));
+            }
+            return Ok(Feed {
+                url,
+                client,
+                kind: FeedKind::Local { path },
+            });
+        }
+        if !url.ends_with("index.json") {
+            return Ok(Feed {
+                url,
+                client,
+                kind: FeedKind::V2,
+            });
+        }
+
+        // Read the service index.
+        let index: ServiceIndex = get_json(&client, &url)?;
+        let package_base_address = match find_resource(&index, "PackageBaseAddress/3.0.0") {
+            Some(address) => address,
+            None => {
+                return Err(format_err!(
+                    "The service index of {} does not contain a package base address.",
+                    url
+                ))
+            }
+        };
+        let registrations_base_url = find_resource(&index, "RegistrationsBaseUrl");
+        return Ok(Feed {
+            url,
+            client,
+            kind: FeedKind::V3 {
+                package_base_address,
+                registrations_base_url,
+            },
+        });
+    }
+
+    /// Gets all available versions of a package.
+    pub fn get_versions(&self, id: &str) -> CakeupResult<Vec<Version>> {
+        return match self.kind {
+            FeedKind::V2 => {
+                let mut versions = Vec::new();
+                let mut url = Some(format!("{}/FindPackagesById()?id='{}'", self.url, id));
+                while let Some(current) = url {
+                    let content = match self.get_string(&current)? {
+                        Some(content) => content,
+                        None => break,
+                    };
+                    let document = roxmltree::Document::parse(&content[..])?;
+                    versions.extend(version::parse_all(&get_properties(&document, "Version")));
+                    url = get_next_link(&document);
+                }
+                Ok(versions)
+            }
+            FeedKind::V3 {
+                ref package_base_address,
+                ..
+            } => {
+                let url = format!("{}/{}/index.json", package_base_address, id.to_lowercase());
+                match self.get_string(&url)? {
+                    Some(content) => {
+                        let index: VersionIndex = serde_json::from_str(&content[..])?;
+                        Ok(version::parse_all(&index.versions))
+                    }
+                    None => Ok(Vec::new()),
+                }
+            }
+            FeedKind::Local { ref path } => {
+                let mut versions = Vec::new();
+                for (version, _) in find_local_packages(path, id)? {
+                    if !versions.contains(&version) {
+                        versions.push(version);
+                    }
+                }
+                Ok(versions)
+            }
+        };
+    }
+
+    /// Gets the URL that a package can be downloaded from.
+    pub fn get_download_url(&self, id: &str, version: &Version) -> String {
+        return match self.kind {
+            FeedKind::V2 => format!("{}/package/{}/{}", self.url, id, version),
+            FeedKind::V3 {
+                ref package_base_address,
+                ..
+            } => {
+                let id = id.to_lowercase();
+                let version = format!("{}", version).to_lowercase();
+                format!(
+                    "{0}/{1}/{2}/{1}.{2}.nupkg",
+                    package_base_address, id, version
+                )
+            }
+            FeedKind::Local { ref path } => {
+                let id = id.to_lowercase();
+                let version = format!("{}", version).to_lowercase();
+                let filename = format!("{}.{}.nupkg", id, version);
+                format!("{}", path.join(&id).join(&version).join(filename).display())
+            }
+        };
+    }
+
+    /// Gets the base64 encoded SHA-512 hash of a package,
+    /// if the package source publishes it.
+    pub fn get_package_hash(&self, id: &str, version: &Version) -> CakeupResult<Option<String>> {
+        let (hash, algorithm) = match self.kind {
+            FeedKind::V2 => {
+                let url = format!("{}/Packages(Id='{}',Version='{}')", self.url, id, version);
+                let content = match self.get_string(&url)? {
+                    Some(content) => content,
+                    None => return Ok(None),
+                };
+                let document = roxmltree::Document::parse(&content[..])?;
+                let hash = get_properties(&document, "PackageHash").pop();
+                let algorithm = get_properties(&document, "PackageHashAlgorithm").pop();
+                match (hash, algorithm) {
+                    (Some(hash), Some(algorithm)) => (hash, algorithm),
+                    _ => return Ok(None),
+                }
+            }
+            FeedKind::V3 {
+                ref registrations_base_url,
+                ..
+            } => {
+                let registrations_base_url = match registrations_base_url {
+                    Some(url) => url,
+                    None => return Ok(None),
+                };
+                let url = format!(
+                    "{}/{}/{}.json",
+                    registrations_base_url,
+                    id.to_lowercase(),
+                    format!("{}", version).to_lowercase()
+                );
+                let leaf: RegistrationLeaf = get_json(&self.client, &url)?;
+                let entry: CatalogEntry = get_json(&self.client, &leaf.catalog_entry)?;
+                (entry.package_hash, entry.package_hash_algorithm)
+            }
+            FeedKind::Local { ref path } => match find_local_hash(path, id, version)? {
+                Some(hash) => (hash, String::from("SHA512")),
+                None => return Ok(None),
+            },
+        };
+        if algorithm.to_uppercase() != "SHA512" {
+            return Ok(None);
+        }
+        return Ok(Some(hash));
+    }
+
+    /// Downloads a package to the specified path.
+    pub fn download(&self, id: &str, version: &Version, path: &Path) -> CakeupResult<()> {
+        if let FeedKind::Local {
+            path: ref directory,
+        } = self.kind
+        {
+            return match find_local_package(directory, id, version)? {
+                Some(package) => {
+                    trace!("Copying {}...", package.display());
+                    fs::copy(&package, path)?;
+                    Ok(())
+                }
+                None => Err(format_err!(
+                    "Could not find {} ({}) in {}.",
+                    id,
+                    version,
+                    self
+                )),
+            };
+        }
+
+        let url = self.get_download_url(id, version);
+        trace!("Downloading {}...", http::redact(&url));
+        return self.client.download(&url, path);
+    }
+
+    fn get_string(&self, url: &String) -> CakeupResult<Option<String>> {
+        trace!("Downloading {}...", http::redact(url));
+        return self.client.try_get_string(url);
+    }
+}
+
+impl fmt::Display for Feed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", http::redact(&self.url));
+    }
+}
+
+pub fn get_user_agent() -> String {
+    return format!("Cakeup NuGet Client/{}", version::VERSION);
+}
+
+/// Opens all configured package sources.
+/// Sources that can't be reached are skipped, and so are
+/// remote sources in offline mode.
+pub fn open_sources(config: &Config) -> CakeupResult<Vec<Feed>> {
+    let sources = get_sources(config)?;
+    let mut feeds = Vec::new();
+    for source in sources.iter() {
+        if config.offline && is_remote(&source.url) {
+            trace!(
+                "Skipping package source {} in offline mode.",
+                http::redact(&source.url)
+            );
+            continue;
+        }
+        match Feed::open(config, source) {
+            Ok(feed) => feeds.push(feed),
+            Err(e) => warn!(
+                "Could not read package source {}. {}",
+                http::redact(&source.url),
+                e
+            ),
+        }
+    }
+    if feeds.is_empty() && !config.offline {
+        return Err(format_err!("None of the package sources could be read."));
+    }
+    return Ok(feeds);
+}
+
+/// Gets all available versions of a package from the provided sources.
+pub fn get_versions(feeds: &Vec<Feed>, id: &str) -> CakeupResult<Vec<Version>> {
+    let mut versions = Vec::new();
+    for feed in feeds.iter() {
+        match feed.get_versions(id) {
+            Ok(found) => {
+                for version in found {
+                    if !versions.contains(&version) {
+                        versions.push(version);
+                    }
+                }
+            }
+            Err(e) => warn!("Could not get versions of {} from {}. {}", id, feed, e),
+        }
+    }
+    return Ok(versions);
+}
+
+/// Gets the latest version of a package from the provided sources,
+/// which is the latest stable version unless prereleases are allowed.
+pub fn get_latest_version(feeds: &Vec<Feed>, id: &str, prerelease: bool) -> CakeupResult<Version> {
+    let versions = get_versions(feeds, id)?;
+    return match version::find_latest(&versions, prerelease) {
+        Some(v) => Ok(v),
+        None if prerelease => Err(format_err!("Could not find a version of {}.", id)),
+        None => Err(format_err!("Could not find a stable version of {}.", id)),
+    };
+}
+
+/// Downloads a package from the first source that has it
+/// and returns the hash published by that source (if any).
+pub fn download_package(
+    feeds: &Vec<Feed>,
+    id: &str,
+    version: &Version,
+    path: &Path,
+) -> CakeupResult<Option<String>> {
+    for feed in feeds.iter() {
+        match feed.download(id, version, path) {
+            Ok(()) => {
+                info!("Downloaded {} ({}) from {}.", id, version, feed);
+            }
+            Err(e) => {
+                // Make sure that authentication problems are visible.
+                match e.downcast_ref::<HttpError>() {
+                    Some(HttpError::AccessDenied { .. }) => {
+                        warn!("Could not download {} from {}. {}", id, feed, e)
+                    }
+                    _ => trace!("Could not download {} from {}. {}", id, feed, e),
+                };
+                continue;
+            }
+        };
+
+        // Get the hash that the package source has published.
+        let hash = match feed.get_package_hash(id, version) {
+            Ok(hash) => hash,
+            Err(e) => {
+                warn!("Could not get the hash of {} from {}. {}", id, feed, e);
+                None
+            }
+        };
+        return Ok(hash);
+    }
+    return Err(format_err!(
+        "Could not find {} ({}) in any package source.",
+        id,
+        version
+    ));
+}
+
+/// Gets the package sources to use. Sources provided to cakeup
+/// take precedence over the ones in NuGet.config.
+fn get_sources(config: &Config) -> CakeupResult<Vec<PackageSource>> {
+    let nuget_config = nuget_config::load(&config.root)?;
+    let mut sources = Vec::new();
+    if !config.sources.is_empty() {
+        for source in config.sources.iter() {
+            sources.push(match nuget_config.find_source(source) {
+                Some(found) => PackageSource {
+                    name: found.name.clone(),
+                    url: found.url.clone(),
+                    credentials: found.credentials.clone(),
+                },
+                None => PackageSource {
+                    name: source.clone(),
+                    url: source.clone(),
+                    credentials: None,
+                },
+            });
+        }
+    } else {
+        sources = nuget_config.sources;
+    }
+
+    if sources.is_empty() {
+        trace!("No package sources configured. Using {}.", DEFAULT_SOURCE);
+        sources.push(PackageSource {
+            name: String::from(DEFAULT_SOURCE),
+            url: String::from(DEFAULT_SOURCE),
+            credentials: None,
+        });
+    }
+    return Ok(sources);
+}
+
+fn is_remote(url: &String) -> bool {
+    let url = url.to_lowercase();
+    return url.starts_with("http://") || url.starts_with("https://");
+}
+
+/// Gets the path of a local package source,
+/// which can also be written as a file:// URL.
+fn get_local_path(url: &String) -> PathBuf {
+    if url.to_lowercase().starts_with("file://") {
+        let path = &url["file://".len()..];
+
+        // Drive letters come after a slash (file:///C:/feed).
+        let bytes = path.as_bytes();
+        if bytes.len() >= 3
+            && bytes[0] == b'/'
+            && bytes[1].is_ascii_alphabetic()
+            && bytes[2] == b':'
+        {
+            return PathBuf::from(&path[1..]);
+        }
+        return PathBuf::from(path);
+    }
+    return PathBuf::from(url);
+}
+
+/// Finds all packages with the provided id in a local package source.
+fn find_local_packages(directory: &Path, id: &str) -> CakeupResult<Vec<(Version, PathBuf)>> {
+    let mut packages = Vec::new();
+    let id = id.to_lowercase();
+
+    // Flat layout: <id>.<version>.nupkg
+    let prefix = format!("{}.", id);
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().to_lowercase();
+        if filename.starts_with(&prefix[..]) && filename.ends_with(".nupkg") {
+            let version = &filename[prefix.len()..filename.len() - ".nupkg".len()];
+            if let Ok(version) = Version::parse(version) {
+                packages.push((version, entry.path()));
+            }
+        }
+    }
+
+    // Hierarchical layout: <id>/<version>/<id>.<version>.nupkg
+    let package_directory = directory.join(&id);
+    if package_directory.is_dir() {
+        for entry in fs::read_dir(&package_directory)? {
+            let entry = entry?;
+            let version = entry.file_name().to_string_lossy().to_lowercase();
+            let package = entry.path().join(format!("{}.{}.nupkg", id, version));
+            if let Ok(version) = Version::parse(&version[..]) {
+                if package.exists() {
+                    packages.push((version, package));
+                }
+            }
+        }
+    }
+
+    return Ok(packages);
+}
+
+fn find_local_package(
+    directory: &Path,
+    id: &str,
+    version: &Version,
+) -> CakeupResult<Option<PathBuf>> {
+    return Ok(find_local_packages(directory, id)?
+        .into_iter()
+        .find(|&(ref v, _)| v == version)
+        .map(|(_, path)| path));
+}
+
+/// Reads the hash of a package in a local package source. Packages
+/// in the hierarchical layout have their hash next to them.
+fn find_local_hash(directory: &Path, id: &str, version: &Version) -> CakeupResult<Option<String>> {
+    let hash_path = match find_local_package(directory, id, version)? {
+        Some(package) => package.with_file_name(format!(
+            "{}.sha512",
+            package.file_name().unwrap().to_string_lossy()
+        )),
+        None => return Ok(None),
+    };
+    if !hash_path.exists() {
+        return Ok(None);
+    }
+    return Ok(Some(fs::read_to_string(&hash_path)?.trim().to_string()));
+}
+
+fn find_resource(index: &ServiceIndex, kind: &str) -> Option<String> {
+    return index
+        .resources
+        .iter()
+        .find(|r| r.kind.starts_with(kind))
+        .map(|r| r.id.trim_end_matches('/').to_string());
+}
+
+fn get_properties(document: &roxmltree::Document, name: &str) -> Vec<String> {
+    return document
+        .descendants()
+        .filter(|n| n.tag_name().name() == name)
+        .filter(|n| {
+            n.parent()
+                .map(|p| p.tag_name().name() == "properties")
+                .unwrap_or(false)
+        })
+        .filter_map(|n| n.text().map(|t| t.to_string()))
+        .collect();
+}
+
+fn get_next_link(document: &roxmltree::Document) -> Option<String> {
+    return document
+        .root_element()
+        .children()
+        .find(|n| n.tag_name().name() == "link" && n.attribute("rel") == Some("next"))
+        .and_then(|n| n.attribute("href"))
+        .map(|href| href.to_string());
+}
+
+fn get_json<T: DeserializeOwned>(client: &http::Client, url: &String) -> CakeupResult<T> {
+    trace!("Downloading {}...", http::redact(url));
+    let content = client.get_string(url)?;
+    return Ok(serde_json::from_str(&content[..])?);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn create_source(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("cakeup-test-feed-{}", name));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        for &(file, content) in files.iter() {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        return root;
+    }
+
+    fn version(value: &str) -> Version {
+        return Version::parse(value).unwrap();
+    }
+
+    #[test]
+    fn local_paths_can_be_file_urls() {
+        assert_eq!(
+            get_local_path(&String::from("packages")),
+            PathBuf::from("packages")
+        );
+        assert_eq!(
+            get_local_path(&String::from("file:///srv/feed")),
+            PathBuf::from("/srv/feed")
+        );
+        assert_eq!(
+            get_local_path(&String::from("FILE:///C:/feed")),
+            PathBuf::from("C:/feed")
+        );
+        assert_eq!(
+            get_local_path(&String::from("file://server/feed")),
+            PathBuf::from("server/feed")
+        );
+    }
+
+    #[test]
+    fn finds_packages_in_flat_layout() {
+        let root = create_source(
+            "flat",
+            &[
+                ("Cake.0.33.0.nupkg", ""),
+                ("cake.0.34.0-beta.nupkg", ""),
+                ("Cake.Core.0.33.0.nupkg", ""),
+                ("Cake.0.35.0.zip", ""),
+            ],
+        );
+        let mut versions: Vec<Version> = find_local_packages(&root, "Cake")
+            .unwrap()
+            .into_iter()
+            .map(|(v, _)| v)
+            .collect();
+        versions.sort();
+        assert_eq!(versions, vec![version("0.33.0"), version("0.34.0-beta")]);
+        assert_eq!(
+            find_local_package(&root, "cake", &version("0.33.0")).unwrap(),
+            Some(root.join("Cake.0.33.0.nupkg"))
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn finds_packages_in_hierarchical_layout() {
+        let root = create_source(
+            "hierarchical",
+            &[
+                ("cake/0.33.0/cake.0.33.0.nupkg", ""),
+                ("cake/0.34.0/cake.nuspec", ""),
+            ],
+        );
+        let packages = find_local_packages(&root, "Cake").unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].0, version("0.33.0"));
+        assert_eq!(
+            packages[0].1,
+            root.join("cake").join("0.33.0").join("cake.0.33.0.nupkg")
+        );
+        assert_eq!(
+            find_local_package(&root, "Cake", &version("0.34.0")).unwrap(),
+            None
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reads_hashes_next_to_packages() {
+        let root = create_source(
+            "hash",
+            &[
+                ("cake/0.33.0/cake.0.33.0.nupkg", ""),
+                ("cake/0.33.0/cake.0.33.0.nupkg.sha512", "abc==\r\n"),
+                ("cake/0.34.0/cake.0.34.0.nupkg", ""),
+            ],
+        );
+        assert_eq!(
+            find_local_hash(&root, "Cake", &version("0.33.0")).unwrap(),
+            Some(String::from("abc=="))
+        );
+        assert_eq!(
+            find_local_hash(&root, "Cake", &version("0.34.0")).unwrap(),
+            None
+        );
+        assert_eq!(
+            find_local_hash(&root, "Cake", &version("0.35.0")).unwrap(),
+            None
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
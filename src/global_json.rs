@@ -0,0 +1,395 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+use serde_json;
+
+use crate::utils::CakeupResult;
+
+pub const GLOBAL_JSON_FILENAME: &'static str = "global.json";
+
+#[derive(Deserialize, Default)]
+struct GlobalJson {
+    sdk: Option<SdkSection>,
+}
+
+#[derive(Deserialize)]
+struct SdkSection {
+    version: Option<String>,
+    #[serde(rename = "rollForward")]
+    roll_forward: Option<String>,
+    #[serde(rename = "allowPrerelease")]
+    allow_prerelease: Option<bool>,
+}
+
+/// The roll forward policies supported by the dotnet host.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RollForward {
+    Patch,
+    Feature,
+    Minor,
+    Major,
+    LatestPatch,
+    LatestFeature,
+    LatestMinor,
+    LatestMajor,
+    Disable,
+}
+
+impl RollForward {
+    fn parse(value: &str) -> Option<RollForward> {
+        return match &value.to_lowercase()[..] {
+            "patch" => Some(RollForward::Patch),
+            "feature" => Some(RollForward::Feature),
+            "minor" => Some(RollForward::Minor),
+            "major" => Some(RollForward::Major),
+            "latestpatch" => Some(RollForward::LatestPatch),
+            "latestfeature" => Some(RollForward::LatestFeature),
+            "latestminor" => Some(RollForward::LatestMinor),
+            "latestmajor" => Some(RollForward::LatestMajor),
+            "disable" => Some(RollForward::Disable),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for RollForward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RollForward::Patch => "patch",
+            RollForward::Feature => "feature",
+            RollForward::Minor => "minor",
+            RollForward::Major => "major",
+            RollForward::LatestPatch => "latestPatch",
+            RollForward::LatestFeature => "latestFeature",
+            RollForward::LatestMinor => "latestMinor",
+            RollForward::LatestMajor => "latestMajor",
+            RollForward::Disable => "disable",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// The SDK required by a global.json file.
+pub struct SdkRequirement {
+    pub path: PathBuf,
+    pub version: Version,
+    pub roll_forward: RollForward,
+    pub allow_prerelease: bool,
+}
+
+impl SdkRequirement {
+    /// Selects an SDK from the provided versions
+    /// the same way as the dotnet host does it.
+    pub fn find_best(&self, versions: &Vec<Version>) -> Option<Version> {
+        let candidates: Vec<&Version> = versions
+            .iter()
+            .filter(|v| self.allow_prerelease || !v.is_prerelease() || **v == self.version)
+            .filter(|v| **v >= self.version)
+            .collect();
+
+        let requested = &self.version;
+        let same_band =
+            |v: &&Version| is_same_minor(v, requested) && get_band(v) == get_band(requested);
+        return match self.roll_forward {
+            RollForward::Disable => find_highest(&candidates, |v| *v == requested),
+            RollForward::Patch => find_highest(&candidates, |v| *v == requested)
+                .or_else(|| find_highest(&candidates, same_band)),
+            RollForward::LatestPatch => find_highest(&candidates, same_band),
+            RollForward::LatestFeature => {
+                find_highest(&candidates, |v| is_same_minor(v, requested))
+            }
+            RollForward::LatestMinor => find_highest(&candidates, |v| v.major == requested.major),
+            RollForward::LatestMajor => find_highest(&candidates, |_| true),
+            RollForward::Feature | RollForward::Minor | RollForward::Major => {
+                // Use the latest patch of the requested feature band, or roll
+                // forward to the closest higher feature band, minor or major.
+                if let Some(version) = find_highest(&candidates, same_band) {
+                    return Some(version);
+                }
+                let scope: fn(&Version, &Version) -> bool = match self.roll_forward {
+                    RollForward::Feature => |v: &Version, r: &Version| is_same_minor(v, r),
+                    RollForward::Minor => |v: &Version, r: &Version| v.major == r.major,
+                    _ => |_: &Version, _: &Version| true,
+                };
+                let closest = candidates
+                    .iter()
+                    .filter(|v| scope(v, requested))
+                    .min_by_key(|v| (v.major, v.minor, get_band(v)))?;
+                find_highest(&candidates, |v| {
+                    is_same_minor(v, closest) && get_band(v) == get_band(closest)
+                })
+            }
+        };
+    }
+}
+
+impl fmt::Display for SdkRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{} (rollForward: {})", self.version, self.roll_forward);
+    }
+}
+
+/// Finds the global.json file that applies to a directory,
+/// walking up the directory tree like the dotnet host does.
+pub fn find_path(root: &Path) -> Option<PathBuf> {
+    return root
+        .ancestors()
+        .map(|directory| directory.join(GLOBAL_JSON_FILENAME))
+        .find(|path| path.is_file());
+}
+
+/// Reads the SDK required by the global.json file
+/// that applies to a directory, if there is one.
+pub fn find_sdk_requirement(root: &Path) -> CakeupResult<Option<SdkRequirement>> {
+    let path = match find_path(root) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    trace!("Reading {}...", path.display());
+    let content = fs::read_to_string(&path)?;
+    let global_json: GlobalJson = match serde_json::from_str(&content[..]) {
+        Ok(global_json) => global_json,
+        Err(e) => return Err(format_err!("Could not parse {}. {}", path.display(), e)),
+    };
+    let sdk = match global_json.sdk {
+        Some(sdk) => sdk,
+        None => return Ok(None),
+    };
+    let version = match sdk.version {
+        Some(version) => match Version::parse(&version[..]) {
+            Ok(version) => version,
+            Err(e) => {
+                return Err(format_err!(
+                    "The SDK version '{}' in {} is not valid. {}",
+                    version,
+                    path.display(),
+                    e
+                ))
+            }
+        },
+        None => return Ok(None),
+    };
+
+    // The dotnet host uses latestPatch unless told otherwise.
+    let roll_forward = match sdk.roll_forward {
+        Some(value) => match RollForward::parse(&value[..]) {
+            Some(roll_forward) => roll_forward,
+            None => {
+                return Err(format_err!(
+                    "The roll forward policy '{}' in {} is not valid.",
+                    value,
+                    path.display()
+                ))
+            }
+        },
+        None => RollForward::LatestPatch,
+    };
+
+    return Ok(Some(SdkRequirement {
+        path,
+        version,
+        roll_forward,
+        allow_prerelease: sdk.allow_prerelease.unwrap_or(true),
+    }));
+}
+
+fn is_same_minor(version: &Version, other: &Version) -> bool {
+    return version.major == other.major && version.minor == other.minor;
+}
+
+/// Gets the feature band of an SDK version, which is
+/// the hundreds of the patch number (2.1.302 is in 2.1.300).
+fn get_band(version: &Version) -> u64 {
+    return version.patch / 100;
+}
+
+fn find_highest<F>(versions: &Vec<&Version>, predicate: F) -> Option<Version>
+where
+    F: Fn(&&Version) -> bool,
+{
+    return versions
+        .iter()
+        .filter(|v| predicate(*v))
+        .max()
+        .map(|v| (*v).clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn versions() -> Vec<Version> {
+        return [
+            "2.1.300",
+            "2.1.302",
+            "2.1.400",
+            "2.1.401",
+            "2.2.100",
+            "2.2.101",
+            "3.0.100",
+            "3.1.100",
+            "3.1.102",
+            "4.0.100-preview1",
+        ]
+        .iter()
+        .map(|v| Version::parse(v).unwrap())
+        .collect();
+    }
+
+    fn find(version: &str, roll_forward: RollForward, allow_prerelease: bool) -> Option<String> {
+        let requirement = SdkRequirement {
+            path: PathBuf::from(GLOBAL_JSON_FILENAME),
+            version: Version::parse(version).unwrap(),
+            roll_forward,
+            allow_prerelease,
+        };
+        return requirement.find_best(&versions()).map(|v| format!("{}", v));
+    }
+
+    fn find_stable(version: &str, roll_forward: RollForward) -> Option<String> {
+        return find(version, roll_forward, false);
+    }
+
+    fn some(version: &str) -> Option<String> {
+        return Some(version.to_string());
+    }
+
+    #[test]
+    fn disable_only_accepts_the_requested_version() {
+        assert_eq!(
+            find_stable("2.1.302", RollForward::Disable),
+            some("2.1.302")
+        );
+        assert_eq!(find_stable("2.1.301", RollForward::Disable), None);
+    }
+
+    #[test]
+    fn patch_prefers_the_requested_version() {
+        assert_eq!(find_stable("2.1.300", RollForward::Patch), some("2.1.300"));
+        assert_eq!(find_stable("2.1.301", RollForward::Patch), some("2.1.302"));
+        assert_eq!(find_stable("2.1.303", RollForward::Patch), None);
+    }
+
+    #[test]
+    fn latest_patch_uses_the_latest_patch_in_the_band() {
+        assert_eq!(
+            find_stable("2.1.300", RollForward::LatestPatch),
+            some("2.1.302")
+        );
+        assert_eq!(find_stable("2.1.303", RollForward::LatestPatch), None);
+    }
+
+    #[test]
+    fn feature_rolls_forward_to_the_closest_feature_band() {
+        assert_eq!(
+            find_stable("2.1.300", RollForward::Feature),
+            some("2.1.302")
+        );
+        assert_eq!(
+            find_stable("2.1.303", RollForward::Feature),
+            some("2.1.401")
+        );
+        assert_eq!(find_stable("2.1.500", RollForward::Feature), None);
+    }
+
+    #[test]
+    fn minor_rolls_forward_to_the_closest_minor_version() {
+        assert_eq!(find_stable("2.1.300", RollForward::Minor), some("2.1.302"));
+        assert_eq!(find_stable("2.1.500", RollForward::Minor), some("2.2.101"));
+        assert_eq!(find_stable("3.1.200", RollForward::Minor), None);
+    }
+
+    #[test]
+    fn major_rolls_forward_to_the_closest_major_version() {
+        assert_eq!(find_stable("2.1.300", RollForward::Major), some("2.1.302"));
+        assert_eq!(find_stable("2.1.500", RollForward::Major), some("2.2.101"));
+        assert_eq!(find_stable("2.3.100", RollForward::Major), some("3.0.100"));
+        assert_eq!(find_stable("3.2.100", RollForward::Major), None);
+    }
+
+    #[test]
+    fn latest_policies_use_the_latest_version_in_scope() {
+        assert_eq!(
+            find_stable("2.1.300", RollForward::LatestFeature),
+            some("2.1.401")
+        );
+        assert_eq!(
+            find_stable("2.1.300", RollForward::LatestMinor),
+            some("2.2.101")
+        );
+        assert_eq!(
+            find_stable("2.1.300", RollForward::LatestMajor),
+            some("3.1.102")
+        );
+        assert_eq!(find_stable("3.1.200", RollForward::LatestMinor), None);
+    }
+
+    #[test]
+    fn prereleases_are_only_used_if_allowed() {
+        assert_eq!(
+            find("2.1.300", RollForward::LatestMajor, true),
+            some("4.0.100-preview1")
+        );
+        assert_eq!(
+            find("2.1.300", RollForward::LatestMajor, false),
+            some("3.1.102")
+        );
+        assert_eq!(find("4.0.100", RollForward::LatestMajor, true), None);
+    }
+
+    #[test]
+    fn requested_prereleases_are_always_allowed() {
+        assert_eq!(
+            find("4.0.100-preview1", RollForward::Disable, false),
+            some("4.0.100-preview1")
+        );
+    }
+
+    fn read_requirement(name: &str, content: &str) -> CakeupResult<Option<SdkRequirement>> {
+        let root = env::temp_dir().join(format!("cakeup-test-global-json-{}", name));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(GLOBAL_JSON_FILENAME), content).unwrap();
+        let result = find_sdk_requirement(&root);
+        fs::remove_dir_all(&root).unwrap();
+        return result;
+    }
+
+    #[test]
+    fn defaults_to_latest_patch_and_allows_prereleases() {
+        let requirement = read_requirement("defaults", r#"{ "sdk": { "version": "2.1.300" } }"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(requirement.version, Version::parse("2.1.300").unwrap());
+        assert!(requirement.roll_forward == RollForward::LatestPatch);
+        assert!(requirement.allow_prerelease);
+    }
+
+    #[test]
+    fn reads_roll_forward_and_allow_prerelease() {
+        let content = r#"{ "sdk": { "version": "2.1.300", "rollForward": "LatestFeature", "allowPrerelease": false } }"#;
+        let requirement = read_requirement("settings", content).unwrap().unwrap();
+        assert!(requirement.roll_forward == RollForward::LatestFeature);
+        assert!(!requirement.allow_prerelease);
+    }
+
+    #[test]
+    fn fails_for_invalid_roll_forward_policies() {
+        let content = r#"{ "sdk": { "version": "2.1.300", "rollForward": "sideways" } }"#;
+        assert!(read_requirement("invalid", content).is_err());
+    }
+
+    #[test]
+    fn ignores_global_json_without_sdk_version() {
+        assert!(read_requirement("no-sdk", r#"{ "msbuild-sdks": {} }"#)
+            .unwrap()
+            .is_none());
+    }
+}
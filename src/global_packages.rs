@@ -0,0 +1,132 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use semver::Version;
+
+use crate::nuget_config;
+use crate::utils::{hash, zip, CakeupResult};
+use crate::Config;
+
+/// Files in a package that NuGet doesn't extract.
+const EXCLUDED_FILES: [&'static str; 3] = ["_rels", "package", "[Content_Types].xml"];
+
+/// Gets the path to the global NuGet packages folder the same way
+/// that NuGet does: the NUGET_PACKAGES environment variable takes
+/// precedence over globalPackagesFolder in NuGet.config.
+pub fn get_path(config: &Config) -> CakeupResult<Option<PathBuf>> {
+    if let Ok(path) = env::var("NUGET_PACKAGES") {
+        if path != "" {
+            return Ok(Some(config.root.join(path)));
+        }
+    }
+    if let Some(path) = nuget_config::load(&config.root)?.global_packages_folder {
+        return Ok(Some(PathBuf::from(path)));
+    }
+    return Ok(dirs::home_dir().map(|home| home.join(".nuget").join("packages")));
+}
+
+/// Gets the directory that a package is (or would be) extracted to.
+pub fn get_package_directory(root: &Path, id: &str, version: &Version) -> PathBuf {
+    let version = format!("{}", version).to_lowercase();
+    return root.join(id.to_lowercase()).join(version);
+}
+
+/// Gets the filename of a package in the global packages folder.
+pub fn get_package_filename(id: &str, version: &Version) -> String {
+    return format!("{}.{}.nupkg", id.to_lowercase(), version).to_lowercase();
+}
+
+/// Adds a package to the global packages folder using the same layout
+/// as NuGet. Failing to do so is not an error since it's only there
+/// to help other tools and repositories.
+pub fn add(config: &Config, id: &str, version: &Version, nupkg: &Path) {
+    let root = match get_path(config) {
+        Ok(Some(root)) => root,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Could not locate the global packages folder. {}", e);
+            return;
+        }
+    };
+    let directory = get_package_directory(&root, id, version);
+    if directory.exists() {
+        return;
+    }
+
+    trace!("Adding {} ({}) to {}...", id, version, root.display());
+    if let Err(e) = try_add(&directory, id, version, nupkg) {
+        warn!(
+            "Could not add {} ({}) to the global packages folder. {}",
+            id, version, e
+        );
+    }
+}
+
+fn try_add(directory: &Path, id: &str, version: &Version, nupkg: &Path) -> CakeupResult<()> {
+    // Extract the package into a temporary directory first
+    // so that NuGet never sees a partially extracted package.
+    let mut temp_filename = directory.file_name().unwrap().to_os_string();
+    temp_filename.push(format!(".{}.tmp", process::id()));
+    let temp = directory.with_file_name(temp_filename);
+    if temp.exists() {
+        fs::remove_dir_all(&temp)?;
+    }
+    fs::create_dir_all(&temp)?;
+    if let Err(e) = extract(&temp, id, version, nupkg) {
+        fs::remove_dir_all(&temp)?;
+        return Err(e);
+    }
+
+    if let Err(e) = fs::rename(&temp, directory) {
+        fs::remove_dir_all(&temp)?;
+        // Someone else added the package while we were extracting it.
+        if directory.exists() {
+            return Ok(());
+        }
+        return Err(e.into());
+    }
+    return Ok(());
+}
+
+fn extract(directory: &Path, id: &str, version: &Version, nupkg: &Path) -> CakeupResult<()> {
+    zip::unzip(nupkg, directory)?;
+    for excluded in EXCLUDED_FILES.iter() {
+        let path = directory.join(excluded);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    // NuGet expects the manifest to be named after the lower cased id.
+    let nuspec = format!("{}.nuspec", id.to_lowercase());
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().into_owned();
+        if filename.to_lowercase().ends_with(".nuspec") && filename != nuspec {
+            fs::rename(entry.path(), directory.join(&nuspec))?;
+        }
+    }
+
+    let filename = get_package_filename(id, version);
+    fs::copy(nupkg, directory.join(&filename))?;
+    let hash = hash::sha512(nupkg)?;
+    fs::write(
+        directory.join(".nupkg.metadata"),
+        format!(
+            "{{\"version\": 2, \"contentHash\": \"{}\", \"source\": null}}",
+            hash
+        ),
+    )?;
+
+    // NuGet considers the package installed once the hash file exists.
+    fs::write(directory.join(format!("{}.sha512", filename)), hash)?;
+    return Ok(());
+}
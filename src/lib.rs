@@ -1,20 +1,37 @@
 #[macro_use]
 extern crate failure;
 #[macro_use]
+extern crate failure_derive;
+#[macro_use]
 extern crate log;
+extern crate roxmltree;
 extern crate semver;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
 
 use std::fs;
 
-pub use crate::config::Config;
+pub use crate::config::{Config, ConfigFile};
+use crate::lockfile::Lockfile;
+use crate::offline::MissingOffline;
 pub use crate::utils::version::VERSION;
 pub use crate::utils::CakeupResult;
 
 mod cake;
 mod config;
 mod dotnet;
+mod feed;
+mod global_json;
+mod global_packages;
 mod host;
+mod lockfile;
 mod nuget;
+mod nuget_config;
+mod offline;
+mod releases;
 mod utils;
 
 pub fn run(config: Config) -> CakeupResult<i32> {
@@ -31,76 +48,126 @@ pub fn run(config: Config) -> CakeupResult<i32> {
         };
     }
 
+    // Read the lock file.
+    let mut lockfile = match Lockfile::load(&config.root) {
+        Ok(lockfile) => lockfile,
+        Err(e) => {
+            return Err(format_err!(
+                "An error occured while reading the lock file. {}",
+                e
+            ))
+        }
+    };
+    let original_lockfile = lockfile.clone();
+
+    // Things that couldn't be installed in offline mode.
+    let mut missing: Vec<String> = vec![];
+
     // NuGet
     if nuget::should_install(&config) {
-        match nuget::install(&config) {
+        match nuget::install(&config, &mut lockfile) {
             Ok(()) => {}
-            Err(e) => {
-                return Err(format_err!(
-                    "An error occured while installing NuGet. {}",
-                    e
-                ))
-            }
+            Err(e) => match e.downcast::<MissingOffline>() {
+                Ok(MissingOffline(what)) => missing.push(what),
+                Err(e) => {
+                    return Err(format_err!(
+                        "An error occured while installing NuGet. {}",
+                        e
+                    ))
+                }
+            },
         };
     }
 
     // .NET Core SDK
     if dotnet::should_install(&config) {
-        match dotnet::install(&config) {
+        match dotnet::install(&config, &mut lockfile) {
             Ok(()) => {}
-            Err(e) => {
-                return Err(format_err!(
-                    "An error occured while installing dotnet. {}",
-                    e
-                ))
-            }
+            Err(e) => match e.downcast::<MissingOffline>() {
+                Ok(MissingOffline(what)) => missing.push(what),
+                Err(e) => {
+                    return Err(format_err!(
+                        "An error occured while installing dotnet. {}",
+                        e
+                    ))
+                }
+            },
         };
     }
 
     // Install Cake.
-    let mut result_code = 0;
+    let mut cake = None;
     if cake::should_install(&config) {
-        let cake = match cake::install(&config) {
+        cake = match cake::install(&config, &mut lockfile) {
             Ok(cake) => cake,
+            Err(e) => match e.downcast::<MissingOffline>() {
+                Ok(MissingOffline(what)) => {
+                    missing.push(what);
+                    None
+                }
+                Err(e) => {
+                    return Err(format_err!(
+                        "An error occured while downloading Cake. {}",
+                        e
+                    ))
+                }
+            },
+        };
+    }
+
+    // Report everything that was missing in offline mode.
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(|m| format!("  - {}", m)).collect();
+        return Err(format_err!(
+            "The following is not available offline:\n{}",
+            missing.join("\n")
+        ));
+    }
+
+    // Update the lock file.
+    if lockfile != original_lockfile {
+        match update_lockfile(&config, &lockfile) {
+            Ok(()) => {}
             Err(e) => {
                 return Err(format_err!(
-                    "An error occured while downloading Cake. {}",
+                    "An error occured while updating the lock file. {}",
                     e
                 ))
             }
         };
+    }
 
-        // Was Cake installed?
-        if cake.is_some() {
-            let cake = cake.unwrap();
-
-            // Bootstrap Cake?
-            if config.bootstrap {
-                match cake.bootstrap(&config) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(format_err!(
-                            "An error occured while bootstrapping Cake script. {}",
-                            e
-                        ))
-                    }
-                };
-            }
+    // Was Cake installed?
+    let mut result_code = 0;
+    if cake.is_some() {
+        let cake = cake.unwrap();
 
-            // Execute Cake script?
-            if config.execute_script {
-                match cake.execute(&config) {
-                    Ok(n) => {
-                        result_code = n;
-                    }
-                    Err(e) => {
-                        return Err(format_err!(
-                            "An error occured while executing Cake script. {}",
-                            e
-                        ))
-                    }
-                };
-            }
+        // Bootstrap Cake?
+        if config.bootstrap {
+            match cake.bootstrap(&config) {
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(format_err!(
+                        "An error occured while bootstrapping Cake script. {}",
+                        e
+                    ))
+                }
+            };
+        }
+
+        // Execute Cake script?
+        if config.execute_script {
+            match cake.execute(&config) {
+                Ok(n) => {
+                    result_code = n;
+                }
+                Err(e) => {
+                    return Err(format_err!(
+                        "An error occured while executing Cake script. {}",
+                        e
+                    ))
+                }
+            };
         }
     }
 
@@ -110,7 +177,17 @@ pub fn run(config: Config) -> CakeupResult<i32> {
 fn create_tools_directory(config: &Config) -> CakeupResult<()> {
     if !config.tools.exists() {
         info!("Creating tools directory...");
-        fs::create_dir(&config.tools.to_str().unwrap())?;
+        fs::create_dir_all(&config.tools)?;
     }
     return Ok(());
 }
+
+fn update_lockfile(config: &Config, lockfile: &Lockfile) -> CakeupResult<()> {
+    if config.locked {
+        return Err(format_err!(
+            "{} needs to be updated but --locked was specified.",
+            lockfile::LOCK_FILENAME
+        ));
+    }
+    return lockfile.save(&config.root);
+}
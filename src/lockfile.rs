@@ -0,0 +1,165 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use std::fs;
+use std::path::Path;
+
+use semver::Version;
+use serde::{Deserialize, Deserializer};
+use toml;
+
+use crate::utils::version::VersionSpec;
+use crate::utils::{CakeupResult, OneOrMany};
+use crate::Config;
+
+pub const LOCK_FILENAME: &'static str = "cakeup.lock";
+
+/// The resolved versions and hashes from a previous run.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nuget: Option<LockEntry>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_entries",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub sdk: Vec<LockEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cake: Option<LockEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runtimes: Vec<LockEntry>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+}
+
+impl Lockfile {
+    pub fn load(root: &Path) -> CakeupResult<Lockfile> {
+        let path = root.join(LOCK_FILENAME);
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+
+        trace!("Reading {}...", path.display());
+        let content = fs::read_to_string(&path)?;
+        return match toml::from_str::<Lockfile>(&content[..]) {
+            Ok(lockfile) => Ok(lockfile),
+            Err(e) => Err(format_err!("Could not parse {}. {}", LOCK_FILENAME, e)),
+        };
+    }
+
+    pub fn save(&self, root: &Path) -> CakeupResult<()> {
+        let path = root.join(LOCK_FILENAME);
+        trace!("Writing {}...", path.display());
+        let content = format!(
+            "# This file is generated by cakeup. Do not edit it manually.\n\n{}",
+            toml::to_string(self)?
+        );
+        fs::write(&path, content)?;
+        return Ok(());
+    }
+}
+
+impl LockEntry {
+    pub fn new(id: Option<&str>, version: &String, sha512: Option<String>) -> Self {
+        return LockEntry {
+            id: id.map(|id| id.to_string()),
+            version: version.clone(),
+            sha512,
+        };
+    }
+}
+
+/// Lock files written before side by side SDKs contain a single entry.
+fn deserialize_entries<'de, D>(deserializer: D) -> Result<Vec<LockEntry>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    return OneOrMany::<LockEntry>::deserialize(deserializer).map(|entries| entries.into_vec());
+}
+
+/// Gets the locked version if it satisfies the requested version.
+pub fn get_locked_version(
+    entry: Option<&LockEntry>,
+    id: Option<&str>,
+    spec: &VersionSpec,
+    prerelease: bool,
+) -> Option<Version> {
+    let entry = match entry {
+        Some(entry) => entry,
+        None => return None,
+    };
+    if entry.id.as_ref().map(|s| &s[..]) != id {
+        return None;
+    }
+    return match Version::parse(&entry.version[..]) {
+        Ok(ref version) if spec.matches(version, prerelease) => Some(version.clone()),
+        _ => None,
+    };
+}
+
+/// Gets the locked hash for a specific version.
+pub fn get_locked_hash(
+    entry: &Option<LockEntry>,
+    id: Option<&str>,
+    version: &String,
+) -> Option<String> {
+    return match entry {
+        Some(entry) if entry.id.as_ref().map(|s| &s[..]) == id && &entry.version == version => {
+            entry.sha512.clone()
+        }
+        _ => None,
+    };
+}
+
+/// Gets the locked hash for a specific version from a list of entries.
+pub fn find_locked_hash(
+    entries: &Vec<LockEntry>,
+    id: Option<&str>,
+    version: &String,
+) -> Option<String> {
+    return entries
+        .iter()
+        .find(|entry| entry.id.as_ref().map(|s| &s[..]) == id && &entry.version == version)
+        .and_then(|entry| entry.sha512.clone());
+}
+
+/// Makes sure that we're allowed to resolve a version
+/// that is not present in the lock file.
+pub fn ensure_unlocked(config: &Config, name: &str) -> CakeupResult<()> {
+    if config.locked {
+        return Err(format_err!(
+            "{} is not locked to a matching version in {}.",
+            name,
+            LOCK_FILENAME
+        ));
+    }
+    return Ok(());
+}
+
+pub fn verify_hash(
+    name: &str,
+    expected: &Option<String>,
+    actual: &Option<String>,
+) -> CakeupResult<()> {
+    if let (Some(expected), Some(actual)) = (expected, actual) {
+        if expected != actual {
+            return Err(format_err!(
+                "The SHA-512 hash of {} does not match the hash in {}.",
+                name,
+                LOCK_FILENAME
+            ));
+        }
+    }
+    return Ok(());
+}
@@ -2,34 +2,106 @@
 // The .NET Foundation licenses this file to you under the MIT license.
 // See the LICENSE file in the project root for more information.
 
+use std::fs;
+use std::path::Path;
 use std::process;
+use std::time::Duration;
 
-use crate::utils::{http, CakeupResult};
+use semver::Version;
+use serde_json;
+
+use crate::lockfile::{self, LockEntry, Lockfile};
+use crate::offline::{self, MissingOffline};
+use crate::utils::cache::Cache;
+use crate::utils::filelock::FileLock;
+use crate::utils::{hash, http, CakeupResult};
 use crate::Config;
 
-pub fn install(config: &Config) -> CakeupResult<()> {
+const TOOLS_URL: &'static str = "https://dist.nuget.org/tools.json";
+
+#[derive(Deserialize)]
+struct Tools {
+    #[serde(rename = "nuget.exe")]
+    releases: Vec<Release>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    version: String,
+    url: String,
+    stage: String,
+}
+
+pub fn install(config: &Config, lockfile: &mut Lockfile) -> CakeupResult<()> {
     if should_install(config) {
         let file = config.tools.join("nuget.exe");
+        let _lock = FileLock::acquire(
+            &config.tools.join(".nuget.lock"),
+            Duration::from_secs(config.lock_timeout),
+        )?;
+        let requested = config.nuget_version.as_ref().unwrap();
+        let locked = get_locked_version(lockfile, requested);
         if !file.exists() {
-            let version = config.nuget_version.as_ref().unwrap();
-            let url = format!(
-                "https://dist.nuget.org/win-x86-commandline/{}/nuget.exe",
-                version
-            );
-            info!("Downloading {}...", url);
-            http::download(&url, &file, Some("Cakeup"))?;
+            let (version, url) = match locked {
+                Some(version) => {
+                    trace!("Using NuGet {} from {}.", version, lockfile::LOCK_FILENAME);
+                    let url = get_download_url(&version);
+                    (version, url)
+                }
+                None => {
+                    lockfile::ensure_unlocked(config, "NuGet")?;
+                    match &requested[..] {
+                        "latest" if config.offline => resolve_cached_latest(config)?,
+                        "latest" => resolve_latest(config)?,
+                        _ => (normalize_version(requested), get_download_url(requested)),
+                    }
+                }
+            };
+            let cache = Cache::new(config);
+            let key = ["nuget", &version[..], "nuget.exe"];
+            if !cache
+                .as_ref()
+                .map_or(Ok(false), |c| c.restore(&key, &file))?
+            {
+                offline::ensure_online(config, format!("NuGet ({})", version))?;
+                info!("Downloading {}...", url);
+                http::Client::new(config)
+                    .with_user_agent("Cakeup")
+                    .download(&url, &file)?;
+                if let Some(ref cache) = cache {
+                    cache.store(&key, &file);
+                }
+            }
+            if let Err(e) = update_lockfile(lockfile, &version, &file) {
+                fs::remove_file(&file)?;
+                return Err(e);
+            }
 
             // Running on non-Windows platform?
             if cfg!(target_os = "linux") || cfg!(target_os = "macos") {
                 // Give the script executable permissions.
                 process::Command::new("chmod")
                     .arg("+x")
-                    .arg(version)
                     .arg(&file)
                     .output()?;
             }
         } else {
             info!("Nuget is already installed.");
+
+            // A file we didn't download can only be checked against the
+            // locked hash, since nothing tells us which version it is.
+            let locked_hash = locked
+                .as_ref()
+                .and_then(|version| lockfile::get_locked_hash(&lockfile.nuget, None, version));
+            match locked {
+                Some(ref version) if locked_hash.is_some() => {
+                    update_lockfile(lockfile, version, &file)?
+                }
+                _ => trace!(
+                    "Not locking NuGet since the version of {} can't be verified.",
+                    file.display()
+                ),
+            };
         }
     }
     return Ok(());
@@ -41,3 +113,85 @@ pub fn should_install(config: &Config) -> bool {
         _ => true,
     };
 }
+
+fn get_locked_version(lockfile: &Lockfile, requested: &String) -> Option<String> {
+    return match lockfile.nuget {
+        Some(ref entry)
+            if requested == "latest" || normalize_version(requested) == entry.version =>
+        {
+            Some(entry.version.clone())
+        }
+        _ => None,
+    };
+}
+
+fn update_lockfile(lockfile: &mut Lockfile, version: &String, file: &Path) -> CakeupResult<()> {
+    let hash = Some(hash::sha512(file)?);
+    let expected_hash = lockfile::get_locked_hash(&lockfile.nuget, None, version);
+    lockfile::verify_hash("NuGet", &expected_hash, &hash)?;
+    lockfile.nuget = Some(LockEntry::new(None, version, hash));
+    return Ok(());
+}
+
+fn normalize_version(version: &String) -> String {
+    return version.trim_start_matches('v').to_string();
+}
+
+fn get_download_url(version: &String) -> String {
+    return format!(
+        "https://dist.nuget.org/win-x86-commandline/v{}/nuget.exe",
+        normalize_version(version)
+    );
+}
+
+fn resolve_cached_latest(config: &Config) -> CakeupResult<(String, String)> {
+    let versions = match Cache::new(config) {
+        Some(cache) => cache
+            .list(&["nuget"])?
+            .iter()
+            .filter(|v| cache.contains(&["nuget", &v[..], "nuget.exe"]))
+            .filter_map(|v| Version::parse(&v[..]).ok())
+            .filter(|v| !v.is_prerelease())
+            .collect(),
+        None => vec![],
+    };
+
+    return match versions.iter().max() {
+        Some(version) => {
+            let version = format!("{}", version);
+            info!("Resolved latest cached version of NuGet to {}.", version);
+            let url = get_download_url(&version);
+            Ok((version, url))
+        }
+        None => Err(MissingOffline(String::from("NuGet (latest)")).into()),
+    };
+}
+
+fn resolve_latest(config: &Config) -> CakeupResult<(String, String)> {
+    info!("Resolving latest version of NuGet...");
+    trace!("Downloading {}...", TOOLS_URL);
+    let content = http::Client::new(config)
+        .with_user_agent("Cakeup")
+        .get_string(&String::from(TOOLS_URL))?;
+    let tools: Tools = serde_json::from_str(&content[..])?;
+
+    // Find the latest blessed release.
+    let latest = tools
+        .releases
+        .iter()
+        .filter(|r| r.stage == "ReleasedAndBlessed")
+        .filter_map(|r| match Version::parse(&r.version[..]) {
+            Ok(v) => Some((v, r)),
+            Err(_) => None,
+        })
+        .filter(|&(ref v, _)| !v.is_prerelease())
+        .max_by(|a, b| a.0.cmp(&b.0));
+
+    return match latest {
+        Some((version, release)) => {
+            info!("Resolved latest version of NuGet to {}.", version);
+            Ok((format!("{}", version), release.url.clone()))
+        }
+        None => Err(format_err!("Could not find a released version of NuGet.")),
+    };
+}
@@ -0,0 +1,457 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use roxmltree;
+
+use crate::utils::http::Credentials;
+use crate::utils::{platform, CakeupResult};
+
+const CONFIG_FILENAMES: [&'static str; 3] = ["nuget.config", "NuGet.config", "NuGet.Config"];
+const NUGET_ORG_NAME: &'static str = "nuget.org";
+const NUGET_ORG_URL: &'static str = "https://api.nuget.org/v3/index.json";
+
+pub struct PackageSource {
+    pub name: String,
+    pub url: String,
+    pub credentials: Option<Credentials>,
+}
+
+/// The merged settings from all NuGet.config files that apply to a directory.
+pub struct NuGetConfig {
+    /// The enabled package sources, closest configuration file first.
+    pub sources: Vec<PackageSource>,
+    /// The globalPackagesFolder setting, resolved to an absolute path.
+    pub global_packages_folder: Option<String>,
+}
+
+impl NuGetConfig {
+    /// Finds an enabled package source by name or URL.
+    pub fn find_source(&self, name_or_url: &str) -> Option<&PackageSource> {
+        let url = name_or_url.trim_end_matches('/');
+        return self.sources.iter().find(|s| {
+            s.name.eq_ignore_ascii_case(name_or_url)
+                || s.url.trim_end_matches('/').eq_ignore_ascii_case(url)
+        });
+    }
+}
+
+/// Items read from a section such as packageSources,
+/// where closer configuration files take precedence.
+#[derive(Default)]
+struct Items {
+    items: Vec<(String, String)>,
+    cleared: bool,
+}
+
+impl Items {
+    /// Merges a section into the items. Relative paths are resolved
+    /// against the directory of the configuration file, if provided.
+    fn merge(&mut self, document: &roxmltree::Document, section: &str, directory: Option<&Path>) {
+        if self.cleared {
+            return;
+        }
+        let section = match find_section(document, section) {
+            Some(section) => section,
+            None => return,
+        };
+        let mut items: Vec<(String, String)> = Vec::new();
+        for node in section.children().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "add" => {
+                    if let (Some(key), Some(value)) =
+                        (node.attribute("key"), node.attribute("value"))
+                    {
+                        let mut value = expand_environment_variables(value);
+                        if let Some(directory) = directory {
+                            value = resolve_path(directory, value);
+                        }
+                        items.push((key.to_string(), value));
+                    }
+                }
+                "clear" => {
+                    // Items from configuration files further away are ignored.
+                    items.clear();
+                    self.cleared = true;
+                }
+                _ => {}
+            }
+        }
+        for (key, value) in items {
+            if self.get(&key).is_none() {
+                self.items.push((key, value));
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&String> {
+        return self
+            .items
+            .iter()
+            .find(|&&(ref k, _)| k.eq_ignore_ascii_case(key))
+            .map(|&(_, ref v)| v);
+    }
+}
+
+pub fn load(root: &Path) -> CakeupResult<NuGetConfig> {
+    let mut sources = Items::default();
+    let mut disabled = Items::default();
+    let mut global_packages_folder: Option<String> = None;
+    let mut credentials: Vec<(String, Credentials)> = Vec::new();
+
+    let user_config = get_user_config_path();
+    let mut paths = get_config_paths(root);
+    if let Some(ref path) = user_config {
+        if path.exists() && !paths.contains(path) {
+            paths.push(path.clone());
+        }
+    }
+
+    for path in paths.iter() {
+        trace!("Reading {}...", path.display());
+        let content = fs::read_to_string(path)?;
+        let document = match roxmltree::Document::parse(&content[..]) {
+            Ok(document) => document,
+            Err(e) => return Err(format_err!("Could not parse {}. {}", path.display(), e)),
+        };
+
+        sources.merge(&document, "packageSources", path.parent());
+        disabled.merge(&document, "disabledPackageSources", None);
+        if global_packages_folder.is_none() {
+            // Relative paths are relative to the configuration file.
+            let mut settings = Items::default();
+            settings.merge(&document, "config", path.parent());
+            global_packages_folder = settings.get("globalPackagesFolder").cloned();
+        }
+        for (name, found) in read_credentials(&document) {
+            if !credentials
+                .iter()
+                .any(|&(ref n, _)| n.eq_ignore_ascii_case(&name))
+            {
+                credentials.push((name, found));
+            }
+        }
+    }
+
+    // NuGet creates a user configuration with nuget.org
+    // as package source if there isn't one.
+    if !user_config.map(|p| p.exists()).unwrap_or(false) && !sources.cleared {
+        if sources.get(NUGET_ORG_NAME).is_none() {
+            sources
+                .items
+                .push((NUGET_ORG_NAME.to_string(), NUGET_ORG_URL.to_string()));
+        }
+    }
+
+    let sources = sources
+        .items
+        .into_iter()
+        .filter(|&(ref name, _)| disabled.get(name).map(|v| v != "true").unwrap_or(true))
+        .map(|(name, url)| PackageSource {
+            credentials: credentials
+                .iter()
+                .find(|&&(ref n, _)| n.eq_ignore_ascii_case(&name))
+                .map(|&(_, ref c)| c.clone()),
+            name,
+            url,
+        })
+        .collect();
+
+    return Ok(NuGetConfig {
+        sources,
+        global_packages_folder,
+    });
+}
+
+/// Gets all NuGet.config files from the root directory and up.
+fn get_config_paths(root: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for directory in root.ancestors() {
+        // Only use one file per directory since file
+        // names are case insensitive on some platforms.
+        let found = CONFIG_FILENAMES
+            .iter()
+            .map(|filename| directory.join(filename))
+            .find(|path| path.exists());
+        if let Some(path) = found {
+            paths.push(path);
+        }
+    }
+    return paths;
+}
+
+fn get_user_config_path() -> Option<PathBuf> {
+    if platform::is_windows().unwrap_or(false) {
+        return env::var("APPDATA")
+            .ok()
+            .map(|p| PathBuf::from(p).join("NuGet").join("NuGet.Config"));
+    }
+    return dirs::home_dir().map(|p| p.join(".nuget").join("NuGet").join("NuGet.Config"));
+}
+
+fn find_section<'a, 'd>(
+    document: &'a roxmltree::Document<'d>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'd>> {
+    return document
+        .root_element()
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name() == name);
+}
+
+fn read_credentials(document: &roxmltree::Document) -> Vec<(String, Credentials)> {
+    let mut result = Vec::new();
+    let section = match find_section(document, "packageSourceCredentials") {
+        Some(section) => section,
+        None => return result,
+    };
+    for source in section.children().filter(|n| n.is_element()) {
+        let name = decode_element_name(source.tag_name().name());
+        let mut username = None;
+        let mut password = None;
+        for node in source.children().filter(|n| n.tag_name().name() == "add") {
+            let value = node
+                .attribute("value")
+                .map(|v| expand_environment_variables(v));
+            match node.attribute("key") {
+                Some("Username") => username = value,
+                Some("ClearTextPassword") => password = value,
+                Some("Password") => warn!(
+                    "Encrypted passwords in NuGet.config are not supported (package source {}).",
+                    name
+                ),
+                _ => {}
+            }
+        }
+        if let (Some(username), Some(password)) = (username, password) {
+            result.push((name, Credentials { username, password }));
+        }
+    }
+    return result;
+}
+
+/// Decodes element names such as My_x0020_Feed
+/// which NuGet uses for names that aren't valid XML.
+fn decode_element_name(name: &str) -> String {
+    let mut result = String::new();
+    let mut rest = name;
+    while let Some(index) = rest.find("_x") {
+        result.push_str(&rest[..index]);
+        let encoded = rest.get(index + 2..index + 7);
+        let decoded = encoded
+            .filter(|e| e.ends_with('_'))
+            .and_then(|e| u32::from_str_radix(&e[..4], 16).ok())
+            .and_then(|c| std::char::from_u32(c));
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[index + 7..];
+            }
+            None => {
+                result.push_str("_x");
+                rest = &rest[index + 2..];
+            }
+        }
+    }
+    result.push_str(rest);
+    return result;
+}
+
+/// Resolves a value that is a relative path (and not a URL)
+/// against the provided directory.
+fn resolve_path(directory: &Path, value: String) -> String {
+    let lower = value.to_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("file://")
+    {
+        return value;
+    }
+    if Path::new(&value).is_relative() {
+        return format!("{}", directory.join(&value).display());
+    }
+    return value;
+}
+
+/// Expands %NAME% environment variables like NuGet does.
+fn expand_environment_variables(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        let end = match rest[start + 1..].find('%') {
+            Some(end) => start + 1 + end,
+            None => break,
+        };
+        result.push_str(&rest[..start]);
+        match env::var(&rest[start + 1..end]) {
+            Ok(expanded) => {
+                result.push_str(&expanded[..]);
+                rest = &rest[end + 1..];
+            }
+            Err(_) => {
+                // Leave unknown variables as they are.
+                result.push('%');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse<'a>(xml: &'a str) -> roxmltree::Document<'a> {
+        return roxmltree::Document::parse(xml).unwrap();
+    }
+
+    #[test]
+    fn closer_files_take_precedence() {
+        let closer = parse(
+            r#"<configuration><packageSources>
+                <add key="MyFeed" value="https://closer.example.com/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let further = parse(
+            r#"<configuration><packageSources>
+                <add key="myfeed" value="https://further.example.com/index.json" />
+                <add key="Other" value="https://other.example.com/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let mut items = Items::default();
+        items.merge(&closer, "packageSources", None);
+        items.merge(&further, "packageSources", None);
+        assert_eq!(items.items.len(), 2);
+        assert_eq!(
+            items.get("MYFEED").unwrap(),
+            "https://closer.example.com/index.json"
+        );
+        assert_eq!(
+            items.get("Other").unwrap(),
+            "https://other.example.com/index.json"
+        );
+    }
+
+    #[test]
+    fn clear_in_closer_file_ignores_further_files() {
+        let closer = parse(
+            r#"<configuration><packageSources>
+                <add key="Before" value="https://before.example.com/index.json" />
+                <clear />
+                <add key="After" value="https://after.example.com/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let further = parse(
+            r#"<configuration><packageSources>
+                <add key="nuget.org" value="https://api.nuget.org/v3/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let mut items = Items::default();
+        items.merge(&closer, "packageSources", None);
+        items.merge(&further, "packageSources", None);
+        assert!(items.cleared);
+        assert_eq!(items.items.len(), 1);
+        assert!(items.get("Before").is_none());
+        assert!(items.get("nuget.org").is_none());
+        assert_eq!(
+            items.get("After").unwrap(),
+            "https://after.example.com/index.json"
+        );
+    }
+
+    #[test]
+    fn clear_in_further_file_keeps_closer_items() {
+        let closer = parse(
+            r#"<configuration><packageSources>
+                <add key="Closer" value="https://closer.example.com/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let further = parse(
+            r#"<configuration><packageSources>
+                <clear />
+                <add key="Further" value="https://further.example.com/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let mut items = Items::default();
+        items.merge(&closer, "packageSources", None);
+        items.merge(&further, "packageSources", None);
+        assert_eq!(items.items.len(), 2);
+    }
+
+    #[test]
+    fn relative_paths_are_resolved_against_the_configuration_file() {
+        let document = parse(
+            r#"<configuration><packageSources>
+                <add key="Local" value="packages" />
+                <add key="Remote" value="https://example.com/index.json" />
+            </packageSources></configuration>"#,
+        );
+        let directory = Path::new("/repo");
+        let mut items = Items::default();
+        items.merge(&document, "packageSources", Some(directory));
+        assert_eq!(
+            items.get("Local").unwrap(),
+            &format!("{}", directory.join("packages").display())
+        );
+        assert_eq!(
+            items.get("Remote").unwrap(),
+            "https://example.com/index.json"
+        );
+    }
+
+    #[test]
+    fn decodes_element_names() {
+        assert_eq!(decode_element_name("My_x0020_Feed"), "My Feed");
+        assert_eq!(
+            decode_element_name("My_x0020_Other_x0020_Feed"),
+            "My Other Feed"
+        );
+        assert_eq!(decode_element_name("Plain"), "Plain");
+        assert_eq!(
+            decode_element_name("Not_xZZZZ_Encoded"),
+            "Not_xZZZZ_Encoded"
+        );
+        assert_eq!(decode_element_name("Truncated_x00"), "Truncated_x00");
+    }
+
+    #[test]
+    fn reads_credentials_with_encoded_names() {
+        env::set_var("CAKEUP_TEST_NUGET_PASSWORD", "secret");
+        let document = parse(
+            r#"<configuration><packageSourceCredentials>
+                <My_x0020_Feed>
+                    <add key="Username" value="user" />
+                    <add key="ClearTextPassword" value="%CAKEUP_TEST_NUGET_PASSWORD%" />
+                </My_x0020_Feed>
+            </packageSourceCredentials></configuration>"#,
+        );
+        let credentials = read_credentials(&document);
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].0, "My Feed");
+        assert_eq!(credentials[0].1.username, "user");
+        assert_eq!(credentials[0].1.password, "secret");
+    }
+
+    #[test]
+    fn expands_known_environment_variables() {
+        env::set_var("CAKEUP_TEST_NUGET_FEED", "feed");
+        assert_eq!(
+            expand_environment_variables("https://%CAKEUP_TEST_NUGET_FEED%.example.com/"),
+            "https://feed.example.com/"
+        );
+    }
+
+    #[test]
+    fn leaves_unknown_environment_variables() {
+        env::remove_var("CAKEUP_TEST_NUGET_UNKNOWN");
+        assert_eq!(
+            expand_environment_variables("%CAKEUP_TEST_NUGET_UNKNOWN%/packages"),
+            "%CAKEUP_TEST_NUGET_UNKNOWN%/packages"
+        );
+        assert_eq!(expand_environment_variables("100%"), "100%");
+    }
+}
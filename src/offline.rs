@@ -0,0 +1,21 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use crate::utils::CakeupResult;
+use crate::Config;
+
+/// Something that would have to be downloaded, but can't
+/// be since cakeup is running in offline mode.
+#[derive(Debug, Fail)]
+#[fail(display = "{} is not available offline.", _0)]
+pub struct MissingOffline(pub String);
+
+/// Makes sure that we're allowed to use the network
+/// for getting something that isn't available locally.
+pub fn ensure_online(config: &Config, what: String) -> CakeupResult<()> {
+    if config.offline {
+        return Err(MissingOffline(what).into());
+    }
+    return Ok(());
+}
@@ -0,0 +1,225 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use semver::Version;
+use serde_json;
+
+use crate::utils::version::{self, VersionSpec};
+use crate::utils::{http, CakeupResult};
+use crate::Config;
+
+const RELEASES_INDEX_URL: &'static str =
+    "https://dotnetcli.blob.core.windows.net/dotnet/release-metadata/releases-index.json";
+
+#[derive(Deserialize)]
+pub struct ReleasesIndex {
+    #[serde(rename = "releases-index")]
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Deserialize)]
+pub struct Channel {
+    #[serde(rename = "channel-version")]
+    pub channel_version: String,
+    #[serde(rename = "latest-sdk")]
+    pub latest_sdk: String,
+    #[serde(rename = "support-phase")]
+    pub support_phase: String,
+    #[serde(rename = "releases.json")]
+    pub releases_url: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChannelReleases {
+    pub releases: Vec<Release>,
+}
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub sdk: Option<Component>,
+    #[serde(default)]
+    pub sdks: Vec<Component>,
+    pub runtime: Option<Component>,
+    #[serde(rename = "aspnetcore-runtime")]
+    pub aspnetcore_runtime: Option<Component>,
+}
+
+/// An SDK or a runtime that is part of a release.
+#[derive(Deserialize)]
+pub struct Component {
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub files: Vec<ReleaseFile>,
+}
+
+#[derive(Deserialize)]
+pub struct ReleaseFile {
+    pub name: String,
+    #[serde(default)]
+    pub rid: String,
+    pub url: String,
+    /// The hex encoded SHA-512 hash of the file.
+    #[serde(default)]
+    pub hash: String,
+}
+
+/// The products that can be installed from the release metadata.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Product {
+    Sdk,
+    Runtime,
+    AspNetCoreRuntime,
+}
+
+impl Product {
+    pub fn get_name(&self) -> &'static str {
+        return match self {
+            Product::Sdk => ".NET Core SDK",
+            Product::Runtime => ".NET Core Runtime",
+            Product::AspNetCoreRuntime => "ASP.NET Core Runtime",
+        };
+    }
+
+    /// Gets the name of the product's archives, which are
+    /// named like dotnet-runtime-linux-x64.tar.gz.
+    pub fn get_file_prefix(&self) -> &'static str {
+        return match self {
+            Product::Sdk => "dotnet-sdk",
+            Product::Runtime => "dotnet-runtime",
+            Product::AspNetCoreRuntime => "aspnetcore-runtime",
+        };
+    }
+}
+
+impl Release {
+    /// Gets the components of the provided product in this release.
+    pub fn get_components(self, product: Product) -> Vec<Component> {
+        return match product {
+            Product::Sdk => self.sdk.into_iter().chain(self.sdks.into_iter()).collect(),
+            Product::Runtime => self.runtime.into_iter().collect(),
+            Product::AspNetCoreRuntime => self.aspnetcore_runtime.into_iter().collect(),
+        };
+    }
+}
+
+impl Channel {
+    pub fn is_preview(&self) -> bool {
+        return self.support_phase == "preview";
+    }
+
+    pub fn get_releases(&self, config: &Config) -> CakeupResult<ChannelReleases> {
+        trace!("Downloading {}...", self.releases_url);
+        let content = http::Client::new(config).get_string(&self.releases_url)?;
+        let releases: ChannelReleases = serde_json::from_str(&content[..])?;
+        return Ok(releases);
+    }
+
+    /// Gets all versions of a product released in this channel.
+    pub fn get_versions(&self, config: &Config, product: Product) -> CakeupResult<Vec<Version>> {
+        let mut versions = Vec::new();
+        for release in self.get_releases(config)?.releases {
+            for component in release.get_components(product) {
+                if let Ok(version) = Version::parse(&component.version[..]) {
+                    if !versions.contains(&version) {
+                        versions.push(version);
+                    }
+                }
+            }
+        }
+        return Ok(versions);
+    }
+}
+
+pub fn get_index(config: &Config) -> CakeupResult<ReleasesIndex> {
+    trace!("Downloading {}...", RELEASES_INDEX_URL);
+    let content = http::Client::new(config).get_string(&String::from(RELEASES_INDEX_URL))?;
+    let index: ReleasesIndex = serde_json::from_str(&content[..])?;
+    return Ok(index);
+}
+
+/// Gets the latest version of the .NET Core SDK, which is
+/// the latest stable version unless prereleases are allowed.
+pub fn get_latest_sdk_version(config: &Config) -> CakeupResult<Version> {
+    let index = get_index(config)?;
+    let versions = index
+        .channels
+        .iter()
+        .filter(|c| config.prerelease || !c.is_preview())
+        .filter_map(|c| Version::parse(&c.latest_sdk[..]).ok())
+        .collect();
+    return match version::find_latest(&versions, config.prerelease) {
+        Some(v) => Ok(v),
+        None if config.prerelease => Err(format_err!("Could not find a .NET Core SDK release.")),
+        None => Err(format_err!(
+            "Could not find a stable .NET Core SDK release."
+        )),
+    };
+}
+
+/// Finds the archive to download for a product version and runtime identifier.
+pub fn find_file(
+    config: &Config,
+    product: Product,
+    version: &Version,
+    rid: &str,
+    extension: &str,
+) -> CakeupResult<Option<ReleaseFile>> {
+    // Products are released in the channel matching their major and minor version.
+    let index = get_index(config)?;
+    let channel_version = format!("{}.{}", version.major, version.minor);
+    let wanted = format!("{}", version);
+
+    // Skip other archives of the product, such as the composite ASP.NET Core Runtime.
+    let names = [
+        format!("{}-{}{}", product.get_file_prefix(), rid, extension),
+        format!(
+            "{}-{}-{}{}",
+            product.get_file_prefix(),
+            version,
+            rid,
+            extension
+        ),
+    ];
+    for channel in index
+        .channels
+        .iter()
+        .filter(|c| c.channel_version == channel_version)
+    {
+        for release in channel.get_releases(config)?.releases {
+            for component in release.get_components(product) {
+                if component.version != wanted {
+                    continue;
+                }
+                return Ok(component
+                    .files
+                    .into_iter()
+                    .find(|f| f.rid == rid && names.contains(&f.name)));
+            }
+        }
+    }
+    return Ok(None);
+}
+
+/// Gets the highest version of a product matching the spec.
+pub fn find_version(
+    config: &Config,
+    product: Product,
+    spec: &VersionSpec,
+) -> CakeupResult<Option<Version>> {
+    // Channels are ordered from newest to oldest, so the first
+    // channel containing a matching version contains the best match.
+    let index = get_index(config)?;
+    for channel in index
+        .channels
+        .iter()
+        .filter(|c| config.prerelease || !c.is_preview())
+    {
+        let versions = channel.get_versions(config, product)?;
+        if let Some(version) = spec.find_best(&versions, config.prerelease) {
+            return Ok(Some(version));
+        }
+    }
+    return Ok(None);
+}
@@ -0,0 +1,122 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::utils::{hash, CakeupResult};
+use crate::Config;
+
+/// A download cache shared by all repositories on the machine.
+/// Every cached file has a .sha512 file next to it which is
+/// used to validate the file before it's used.
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    pub fn new(config: &Config) -> Option<Cache> {
+        let root = match config.cache {
+            Some(ref path) => Some(path.clone()),
+            None => dirs::cache_dir().map(|p| p.join("cakeup")),
+        };
+        return root.map(|root| Cache { root });
+    }
+
+    /// Copies a cached file to the specified path.
+    /// Returns false if the file isn't cached or if the cached file is invalid.
+    pub fn restore(&self, key: &[&str], path: &Path) -> CakeupResult<bool> {
+        let cached = self.get_path(key);
+        let hash_path = get_hash_path(&cached);
+        if !cached.exists() || !hash_path.exists() {
+            return Ok(false);
+        }
+
+        let expected_hash = fs::read_to_string(&hash_path)?;
+        if !hash::verify_sha512(&cached, &expected_hash[..])? {
+            // Another process might have replaced the file but not yet its hash.
+            let current_hash = fs::read_to_string(&hash_path)?;
+            if current_hash != expected_hash && hash::verify_sha512(&cached, &current_hash[..])? {
+                trace!("Copying {} from cache...", cached.display());
+                fs::copy(&cached, path)?;
+                return Ok(true);
+            }
+            warn!("Removing corrupt file {} from cache.", cached.display());
+            fs::remove_file(&cached)?;
+            fs::remove_file(&hash_path)?;
+            return Ok(false);
+        }
+
+        trace!("Copying {} from cache...", cached.display());
+        fs::copy(&cached, path)?;
+        return Ok(true);
+    }
+
+    /// Gets the names of all cached entries below the provided key.
+    pub fn list(&self, key: &[&str]) -> CakeupResult<Vec<String>> {
+        let mut names = Vec::new();
+        let path = self.get_path(key);
+        if !path.is_dir() {
+            return Ok(names);
+        }
+        for entry in fs::read_dir(&path)? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        return Ok(names);
+    }
+
+    /// Returns whether or not a file has been cached.
+    pub fn contains(&self, key: &[&str]) -> bool {
+        let cached = self.get_path(key);
+        return cached.exists() && get_hash_path(&cached).exists();
+    }
+
+    /// Adds a file to the cache. Failing to do so is not an error
+    /// since the cache is only there to speed things up.
+    pub fn store(&self, key: &[&str], path: &Path) {
+        if let Err(e) = self.try_store(key, path) {
+            warn!("Could not add {} to the cache. {}", path.display(), e);
+        }
+    }
+
+    fn try_store(&self, key: &[&str], path: &Path) -> CakeupResult<()> {
+        let cached = self.get_path(key);
+        fs::create_dir_all(cached.parent().unwrap())?;
+
+        // Write to temporary files first so that other processes
+        // never see a partially written file or hash. The hash is
+        // moved into place last since it's what makes the file valid.
+        let temp = get_temp_path(&cached);
+        let hash_path = get_hash_path(&cached);
+        let temp_hash_path = get_temp_path(&hash_path);
+        fs::copy(path, &temp)?;
+        fs::write(&temp_hash_path, hash::sha512(&temp)?)?;
+        fs::rename(&temp, &cached)?;
+        fs::rename(&temp_hash_path, &hash_path)?;
+
+        trace!("Added {} to cache.", cached.display());
+        return Ok(());
+    }
+
+    fn get_path(&self, key: &[&str]) -> PathBuf {
+        let mut path = self.root.clone();
+        for part in key {
+            path.push(part.to_lowercase());
+        }
+        return path;
+    }
+}
+
+fn get_temp_path(path: &Path) -> PathBuf {
+    let mut filename = path.file_name().unwrap().to_os_string();
+    filename.push(format!(".{}.tmp", process::id()));
+    return path.with_file_name(filename);
+}
+
+fn get_hash_path(path: &Path) -> PathBuf {
+    let mut filename = path.file_name().unwrap().to_os_string();
+    filename.push(".sha512");
+    return path.with_file_name(filename);
+}
@@ -0,0 +1,81 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+extern crate fs2;
+
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use self::fs2::FileExt;
+
+use crate::utils::CakeupResult;
+
+/// An advisory lock that prevents several cakeup processes
+/// from installing the same thing at the same time.
+/// The lock is released when dropped.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    pub fn acquire(path: &Path, timeout: Duration) -> CakeupResult<FileLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => break,
+                Err(ref e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {}
+                Err(e) => return Err(e.into()),
+            };
+
+            let owner = get_owner(path);
+            if started.elapsed() >= timeout {
+                return Err(format_err!(
+                    "Timed out waiting for {} which is locked by {}.",
+                    path.display(),
+                    owner
+                ));
+            }
+            if !waiting {
+                info!("Waiting for {} to finish installing...", owner);
+                trace!("Waiting for lock on {}...", path.display());
+                waiting = true;
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+
+        // Let others know who is holding the lock. This is written to a separate
+        // file since a locked file can't be read on all platforms.
+        fs::write(get_owner_path(path), format!("{}", process::id()))?;
+        return Ok(FileLock { file });
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+fn get_owner(path: &Path) -> String {
+    return match fs::read_to_string(get_owner_path(path)) {
+        Ok(ref pid) if pid.trim() != "" => format!("process {}", pid.trim()),
+        _ => String::from("another process"),
+    };
+}
+
+fn get_owner_path(path: &Path) -> PathBuf {
+    let mut filename = path.file_name().unwrap_or_default().to_os_string();
+    filename.push(".owner");
+    return path.with_file_name(filename);
+}
@@ -0,0 +1,47 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+extern crate base64;
+extern crate sha2;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use self::sha2::{Digest, Sha512};
+
+use crate::utils::CakeupResult;
+
+/// Calculates the base64 encoded SHA-512 hash of a file,
+/// which is the same format that NuGet uses.
+pub fn sha512(path: &Path) -> CakeupResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha512::new();
+    io::copy(&mut file, &mut hasher)?;
+    return Ok(base64::encode(&hasher.result()[..]));
+}
+
+/// Calculates the hex encoded SHA-512 hash of a file,
+/// which is the format used by the .NET Core release metadata.
+pub fn sha512_hex(path: &Path) -> CakeupResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha512::new();
+    io::copy(&mut file, &mut hasher)?;
+    let hash: Vec<String> = hasher
+        .result()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    return Ok(hash.join(""));
+}
+
+/// Checks whether or not a file matches a base64 encoded SHA-512 hash.
+pub fn verify_sha512(path: &Path, expected: &str) -> CakeupResult<bool> {
+    return Ok(sha512(path)? == expected.trim());
+}
+
+/// Checks whether or not a file matches a hex encoded SHA-512 hash.
+pub fn verify_sha512_hex(path: &Path, expected: &str) -> CakeupResult<bool> {
+    return Ok(sha512_hex(path)? == expected.trim().to_lowercase());
+}
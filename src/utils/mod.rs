@@ -2,11 +2,32 @@
 // The .NET Foundation licenses this file to you under the MIT license.
 // See the LICENSE file in the project root for more information.
 
+pub mod cache;
+pub mod filelock;
+pub mod hash;
 pub mod http;
 pub mod platform;
+pub mod progress;
 pub mod version;
 pub mod zip;
 
 use failure::Error;
 
 pub type CakeupResult<T> = Result<T, Error>;
+
+/// A setting that can be either a single value or a list of values.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        return match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        };
+    }
+}
@@ -1,33 +1,60 @@
-use crate::utils::CakeupResult;
-
-#[derive(PartialEq)]
-pub enum Platform {
-    Windows,
-    Linux,
-    MacOS,
-}
-
-pub fn is_windows() -> CakeupResult<bool> {
-    let platform = get_platform()?;
-    return Ok(platform == Platform::Windows);
-}
-
-pub fn get_platform_name() -> CakeupResult<String> {
-    return match get_platform() {
-        Ok(Platform::Windows) => Ok(String::from("windows")),
-        Ok(Platform::Linux) => Ok(String::from("linux")),
-        Ok(Platform::MacOS) => Ok(String::from("macos")),
-        Err(e) => Err(e),
-    };
-}
-
-pub fn get_platform() -> CakeupResult<Platform> {
-    if cfg!(target_os = "windows") {
-        return Ok(Platform::Windows);
-    } else if cfg!(target_os = "linux") {
-        return Ok(Platform::Linux);
-    } else if cfg!(target_os = "macos") {
-        return Ok(Platform::MacOS);
-    }
-    return Err(format_err!("Could not get platform."));
-}
+use std::path::Path;
+
+use crate::utils::CakeupResult;
+
+#[derive(PartialEq)]
+pub enum Platform {
+    Windows,
+    Linux,
+    MacOS,
+}
+
+pub fn is_windows() -> CakeupResult<bool> {
+    let platform = get_platform()?;
+    return Ok(platform == Platform::Windows);
+}
+
+pub fn get_platform_name() -> CakeupResult<String> {
+    return match get_platform() {
+        Ok(Platform::Windows) => Ok(String::from("windows")),
+        Ok(Platform::Linux) => Ok(String::from("linux")),
+        Ok(Platform::MacOS) => Ok(String::from("macos")),
+        Err(e) => Err(e),
+    };
+}
+
+pub fn get_platform() -> CakeupResult<Platform> {
+    if cfg!(target_os = "windows") {
+        return Ok(Platform::Windows);
+    } else if cfg!(target_os = "linux") {
+        return Ok(Platform::Linux);
+    } else if cfg!(target_os = "macos") {
+        return Ok(Platform::MacOS);
+    }
+    return Err(format_err!("Could not get platform."));
+}
+
+/// Gets the .NET runtime identifier (such as linux-x64)
+/// of the platform and architecture we're running on.
+pub fn get_runtime_identifier() -> CakeupResult<String> {
+    let os = match get_platform()? {
+        Platform::Windows => "win",
+        Platform::Linux if Path::new("/etc/alpine-release").exists() => "linux-musl",
+        Platform::Linux => "linux",
+        Platform::MacOS => "osx",
+    };
+    let arch = if cfg!(target_arch = "x86_64") {
+        "x64"
+    } else if cfg!(target_arch = "x86") {
+        "x86"
+    } else if cfg!(target_arch = "aarch64") {
+        "arm64"
+    } else if cfg!(target_arch = "arm") {
+        "arm"
+    } else {
+        return Err(format_err!(
+            "The .NET Core SDK is not available for this architecture."
+        ));
+    };
+    return Ok(format!("{}-{}", os, arch));
+}
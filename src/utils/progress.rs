@@ -0,0 +1,182 @@
+// Licensed to the .NET Foundation under one or more agreements.
+// The .NET Foundation licenses this file to you under the MIT license.
+// See the LICENSE file in the project root for more information.
+
+extern crate atty;
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Downloads finishing faster than this are not reported at all.
+const INITIAL_DELAY: u64 = 1;
+/// The number of milliseconds between redraws of the progress bar.
+const REDRAW_INTERVAL: u64 = 100;
+/// The number of seconds between log lines when not attached to a terminal.
+const LOG_INTERVAL: u64 = 10;
+/// The width of the progress bar in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Reports the progress of a download, either as a progress bar
+/// when stdout is a terminal or as periodic log lines otherwise.
+pub struct Progress {
+    name: String,
+    interactive: bool,
+    offset: u64,
+    started: Instant,
+    last_report: Option<Instant>,
+    last_percent: u64,
+}
+
+impl Progress {
+    /// Creates a progress reporter for a download
+    /// that was resumed at the provided offset.
+    pub fn new(name: &str, offset: u64) -> Self {
+        return Progress {
+            name: name.to_string(),
+            interactive: atty::is(atty::Stream::Stdout),
+            offset,
+            started: Instant::now(),
+            last_report: None,
+            last_percent: 0,
+        };
+    }
+
+    /// Updates the progress with the values reported by curl,
+    /// which are relative to where the download was resumed.
+    pub fn update(&mut self, total: f64, downloaded: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.started);
+        if elapsed < Duration::from_secs(INITIAL_DELAY) {
+            return;
+        }
+
+        let interval = if self.interactive {
+            Duration::from_millis(REDRAW_INTERVAL)
+        } else {
+            Duration::from_secs(LOG_INTERVAL)
+        };
+        // Curl reports a total of zero while the size of the download is unknown.
+        let percent = match total as u64 {
+            0 => None,
+            total => get_percent(self.offset + total, self.offset + downloaded as u64),
+        };
+        let due = match self.last_report {
+            None => true,
+            Some(last) => now.duration_since(last) >= interval,
+        };
+
+        // Log lines are written for every ten percent, or
+        // periodically if the size of the download is unknown.
+        if !self.interactive {
+            let step = percent.map(|p| p / 10 * 10);
+            match step {
+                Some(step) if step <= self.last_percent => return,
+                None if !due => return,
+                _ => {}
+            };
+            self.last_percent = step.unwrap_or(0);
+        } else if !due {
+            return;
+        }
+
+        self.last_report = Some(now);
+        self.report(total as u64, downloaded as u64, elapsed);
+    }
+
+    /// Finishes the progress bar, if one has been drawn.
+    pub fn finish(&mut self) {
+        if self.interactive && self.last_report.is_some() {
+            println!();
+        }
+    }
+
+    fn report(&self, total: u64, downloaded: u64, elapsed: Duration) {
+        let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_millis()) / 1000.0;
+        let rate = downloaded as f64 / seconds;
+        let total = if total > 0 {
+            Some(self.offset + total)
+        } else {
+            None
+        };
+        let downloaded = self.offset + downloaded;
+
+        if !self.interactive {
+            match (total, get_percent(total.unwrap_or(0), downloaded)) {
+                (Some(total), Some(percent)) => info!(
+                    "Downloading {}: {}% ({} of {}, {}/s)",
+                    self.name,
+                    percent,
+                    format_size(downloaded),
+                    format_size(total),
+                    format_size(rate as u64)
+                ),
+                _ => info!(
+                    "Downloading {}: {} ({}/s)",
+                    self.name,
+                    format_size(downloaded),
+                    format_size(rate as u64)
+                ),
+            };
+            return;
+        }
+
+        let line = match total {
+            Some(total) => {
+                let filled = (downloaded * BAR_WIDTH as u64 / total) as usize;
+                let filled = if filled > BAR_WIDTH {
+                    BAR_WIDTH
+                } else {
+                    filled
+                };
+                let eta = if rate > 0.0 {
+                    format_duration((total.saturating_sub(downloaded) as f64 / rate) as u64)
+                } else {
+                    String::from("--:--")
+                };
+                format!(
+                    "{} [{}{}] {} / {}  {}/s  ETA {}",
+                    self.name,
+                    "=".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    format_size(downloaded),
+                    format_size(total),
+                    format_size(rate as u64),
+                    eta
+                )
+            }
+            None => format!(
+                "{} {}  {}/s",
+                self.name,
+                format_size(downloaded),
+                format_size(rate as u64)
+            ),
+        };
+
+        // Overwrite the previous line.
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\r{}\x1b[K", line);
+        let _ = stdout.flush();
+    }
+}
+
+fn get_percent(total: u64, downloaded: u64) -> Option<u64> {
+    if total == 0 {
+        return None;
+    }
+    let percent = downloaded * 100 / total;
+    return Some(if percent > 100 { 100 } else { percent });
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        return format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0));
+    }
+    if bytes >= 1024 {
+        return format!("{:.1} KB", bytes as f64 / 1024.0);
+    }
+    return format!("{} B", bytes);
+}
+
+fn format_duration(seconds: u64) -> String {
+    return format!("{}:{:02}", seconds / 60, seconds % 60);
+}
@@ -2,5 +2,199 @@
 // The .NET Foundation licenses this file to you under the MIT license.
 // See the LICENSE file in the project root for more information.
 
+use std::fmt;
+
+use semver::{ReqParseError, Version, VersionReq};
+
 // Embed the version number.
-pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
\ No newline at end of file
+pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+/// A version as requested by the user.
+pub enum VersionSpec {
+    Latest,
+    Exact(Version),
+    Range(VersionReq),
+}
+
+impl VersionSpec {
+    pub fn parse(input: &str) -> Result<VersionSpec, ReqParseError> {
+        if input == "latest" {
+            return Ok(VersionSpec::Latest);
+        }
+        if let Ok(version) = Version::parse(input) {
+            return Ok(VersionSpec::Exact(version));
+        }
+        // Floating versions such as 0.28.*, 2.1.x or ^0.28.
+        return Ok(VersionSpec::Range(VersionReq::parse(input)?));
+    }
+
+    /// Checks whether a version matches the spec. Unless prereleases
+    /// are allowed, they only match when explicitly asked for.
+    pub fn matches(&self, version: &Version, prerelease: bool) -> bool {
+        return match self {
+            VersionSpec::Latest => prerelease || !version.is_prerelease(),
+            VersionSpec::Exact(ref v) => v == version,
+            VersionSpec::Range(ref req) => {
+                req.matches(version)
+                    || (prerelease && version.is_prerelease() && matches_prerelease(req, version))
+            }
+        };
+    }
+
+    /// Gets the highest version matching the spec.
+    pub fn find_best(&self, versions: &Vec<Version>, prerelease: bool) -> Option<Version> {
+        return versions
+            .iter()
+            .filter(|v| self.matches(v, prerelease))
+            .max()
+            .map(|v| v.clone());
+    }
+}
+
+impl fmt::Display for VersionSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            VersionSpec::Latest => write!(f, "latest"),
+            VersionSpec::Exact(ref v) => write!(f, "{}", v),
+            VersionSpec::Range(ref req) => write!(f, "{}", req),
+        };
+    }
+}
+
+/// Gets the highest version from the provided versions,
+/// ignoring prereleases unless they're allowed.
+pub fn find_latest(versions: &Vec<Version>, prerelease: bool) -> Option<Version> {
+    return versions
+        .iter()
+        .filter(|v| prerelease || !v.is_prerelease())
+        .max()
+        .map(|v| v.clone());
+}
+
+/// Gets the release a prerelease version precedes (3.0.100 for 3.0.100-preview).
+fn get_release(version: &Version) -> Version {
+    return Version::new(version.major, version.minor, version.patch);
+}
+
+/// Parses all valid semantic versions, ignoring the ones that are not.
+pub fn parse_all(versions: &Vec<String>) -> Vec<Version> {
+    return versions
+        .iter()
+        .filter_map(|v| Version::parse(&v[..]).ok())
+        .collect();
+}
+
+/// Checks whether a prerelease is within a range. Ranges only match
+/// prereleases of the versions they name (such as ^0.33.0-alpha),
+/// so the prerelease is compared to each comparator of the range.
+fn matches_prerelease(req: &VersionReq, version: &Version) -> bool {
+    // The comparators are only applied to a prerelease if one of them
+    // names a prerelease of the same release, which >=x.y.z-0 always does.
+    let release = get_release(version);
+    let text = format!("{}", req);
+    let mut comparators: Vec<&str> = text.split(", ").filter(|c| *c != "*").collect();
+    let any_prerelease = format!(">={}-0", release);
+    comparators.push(&any_prerelease[..]);
+    match VersionReq::parse(&comparators.join(", ")[..]) {
+        Ok(ref any) if any.matches(version) => {}
+        _ => return false,
+    };
+
+    // Prereleases precede their release, so they're below any lower
+    // bound on it, even though ^ and ~ would still accept them.
+    return !comparators
+        .iter()
+        .any(|comparator| get_lower_bound(comparator) == Some(release.clone()));
+}
+
+/// Gets the release a comparator starts at (3.0.0 for ^3.0 or 3.0.*),
+/// if it has an inclusive lower bound which isn't a prerelease.
+fn get_lower_bound(comparator: &str) -> Option<Version> {
+    let version = comparator.trim_start_matches(|c| c == '=' || c == '~' || c == '^');
+    if version.starts_with(|c| c == '<' || c == '>') || version.contains('-') {
+        return None;
+    }
+    let mut parts = version.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().and_then(|p| p).unwrap_or(0);
+    let patch = parts.next().and_then(|p| p).unwrap_or(0);
+    return Some(Version::new(major, minor, patch));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(spec: &str, version: &str, prerelease: bool) -> bool {
+        let spec = VersionSpec::parse(spec).unwrap();
+        return spec.matches(&Version::parse(version).unwrap(), prerelease);
+    }
+
+    #[test]
+    fn exact_matches_only_that_version() {
+        assert!(matches("3.0.100", "3.0.100", false));
+        assert!(!matches("3.0.100", "3.0.100-preview-010184", true));
+        assert!(matches(
+            "3.0.100-preview-010184",
+            "3.0.100-preview-010184",
+            false
+        ));
+    }
+
+    #[test]
+    fn latest_matches_prereleases_when_allowed() {
+        assert!(matches("latest", "3.0.100", false));
+        assert!(!matches("latest", "3.0.100-preview-010184", false));
+        assert!(matches("latest", "3.0.100-preview-010184", true));
+    }
+
+    #[test]
+    fn caret_matches_prereleases_within_range() {
+        assert!(matches("^3.0.100", "3.0.102", false));
+        assert!(!matches("^3.0.100", "3.0.102-preview", false));
+        assert!(matches("^3.0.100", "3.0.102-preview", true));
+        assert!(matches("^3.0.100", "3.1.100-preview", true));
+        assert!(!matches("^3.0.100", "3.0.100-preview", true));
+        assert!(!matches("^3.0.100", "4.0.100-preview", true));
+        assert!(!matches("^3.0", "3.0.0-preview", true));
+        assert!(matches("^0.33.0-alpha", "0.33.0-beta", false));
+    }
+
+    #[test]
+    fn lower_bound_excludes_its_prereleases() {
+        assert!(matches(">=3.0.100", "3.0.100", false));
+        assert!(!matches(">=3.0.100", "3.0.101-preview", false));
+        assert!(matches(">=3.0.100", "3.0.101-preview", true));
+        assert!(!matches(">=3.0.100", "3.0.100-preview-010184", true));
+        assert!(!matches(">=3.0.100, <3.1", "3.1.100-preview", true));
+        assert!(matches(">=3.0.100, <3.1", "3.0.101-preview", true));
+    }
+
+    #[test]
+    fn wildcard_matches_prereleases_when_allowed() {
+        assert!(matches("*", "3.0.100", false));
+        assert!(!matches("*", "3.0.100-preview", false));
+        assert!(matches("*", "3.0.100-preview", true));
+        assert!(matches("3.0.*", "3.0.100-preview", true));
+        assert!(!matches("3.0.*", "3.0.0-preview", true));
+    }
+
+    #[test]
+    fn find_best_picks_highest_match() {
+        let versions = parse_all(&vec![
+            "3.0.100".to_string(),
+            "3.0.101-preview".to_string(),
+            "3.1.100-preview".to_string(),
+            "4.0.100".to_string(),
+        ]);
+        let spec = VersionSpec::parse("^3.0.100").unwrap();
+        assert_eq!(
+            spec.find_best(&versions, false),
+            Some(Version::parse("3.0.100").unwrap())
+        );
+        assert_eq!(
+            spec.find_best(&versions, true),
+            Some(Version::parse("3.1.100-preview").unwrap())
+        );
+    }
+}